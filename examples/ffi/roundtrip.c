@@ -0,0 +1,49 @@
+/*
+ * Round trip through the C API: monitor two children and wait for both.
+ *
+ *   cargo rustc --release --lib --features ffi --crate-type staticlib
+ *   cc -Iinclude examples/ffi/roundtrip.c target/release/libpid_set.a -o roundtrip
+ *   ./roundtrip
+ */
+#include <errno.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#include "pid_set.h"
+
+static pid_t spawn_exit(int code) {
+    pid_t pid = fork();
+    if (pid == 0) {
+        usleep(100 * 1000);
+        _exit(code);
+    }
+    return pid;
+}
+
+int main(void) {
+    uint32_t first = (uint32_t)spawn_exit(0);
+    PidSet *set = pidset_new(&first, 1);
+    if (set == NULL) {
+        return EXIT_FAILURE;
+    }
+
+    int err = pidset_add(set, (uint32_t)spawn_exit(1));
+    if (err != 0) {
+        fprintf(stderr, "pidset_add: %d\n", err);
+        return EXIT_FAILURE;
+    }
+
+    uint32_t pid;
+    while ((err = pidset_wait_any(set, &pid)) == 0) {
+        int status;
+        waitpid((pid_t)pid, &status, 0);
+        printf("pid %u exited with %d\n", pid, WEXITSTATUS(status));
+    }
+    if (err != -ECHILD) {
+        fprintf(stderr, "pidset_wait_any: %d\n", err);
+        return EXIT_FAILURE;
+    }
+    return pidset_close(set) == 0 ? EXIT_SUCCESS : EXIT_FAILURE;
+}
@@ -0,0 +1,33 @@
+/* C API of the pid_set crate, built with the `ffi` feature. */
+#ifndef PID_SET_H
+#define PID_SET_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+/* An opaque set of monitored PIDs. */
+typedef struct PidSet PidSet;
+
+/*
+ * Creates a set monitoring the `len` PIDs pointed to by `pids`, which are
+ * copied. Returns NULL if `pids` is NULL while `len` is not 0. The set must be
+ * freed with pidset_close().
+ */
+PidSet *pidset_new(const uint32_t *pids, size_t len);
+
+/* Starts monitoring `pid`. Returns 0 or a negated errno value. */
+int pidset_add(PidSet *set, uint32_t pid);
+
+/*
+ * Blocks until one monitored PID exits and stores it in `pid`. Returns 0,
+ * -ECHILD if no PIDs are left, or another negated errno value.
+ */
+int pidset_wait_any(PidSet *set, uint32_t *pid);
+
+/*
+ * Closes every file descriptor owned by the set and frees it, even on error.
+ * Returns 0 or a negated errno value.
+ */
+int pidset_close(PidSet *set);
+
+#endif
@@ -1,19 +1,29 @@
+#[cfg(target_os = "linux")]
+use pid_set::{PidSet, PidSetError};
+
+#[cfg(target_os = "linux")]
 fn sleep_cmd(duration: &str) -> std::process::Command {
     let mut cmd1 = std::process::Command::new("sleep");
     cmd1.arg(duration);
     cmd1
 }
 
-fn main() {
-    let mut pid_set = pid_set::PidSet::new([
+#[cfg(target_os = "linux")]
+fn main() -> Result<(), PidSetError> {
+    let mut pid_set = PidSet::try_new([
         sleep_cmd("1").spawn().unwrap().id(),
         sleep_cmd("3").spawn().unwrap().id(),
         sleep_cmd("3").spawn().unwrap().id(),
         sleep_cmd("3").spawn().unwrap().id(),
         sleep_cmd("3").spawn().unwrap().id(),
-    ]);
+    ])?;
 
-    pid_set.wait_all().unwrap();
+    pid_set.wait_all()?;
 
-    pid_set.close().unwrap()
+    pid_set.close()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("pid-set is only supported on Linux");
 }
@@ -0,0 +1,232 @@
+//! Builder for configuring a [`PidSet`] beyond the defaults of [`PidSet::new`].
+
+use std::{collections::hash_map::RandomState, hash::BuildHasher, time::Duration};
+
+use crate::{ErrorAction, ErrorHook, PidSet, PidSetError, PID};
+
+/// How many times, and how soon, to retry a `pidfd_open` failing with `ESRCH`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Retry {
+    pub(crate) attempts: u32,
+    pub(crate) delay: Duration,
+}
+
+/// Options shared by every `PidSet`, set through [`PidSetBuilder`].
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Options {
+    pub(crate) check_fd_limit: bool,
+    pub(crate) max_events: Option<usize>,
+    pub(crate) retain_exited: bool,
+    pub(crate) expect_autoreaped: bool,
+    pub(crate) oneshot: bool,
+    pub(crate) pidfd_retry: Option<Retry>,
+    pub(crate) strict_tokens: bool,
+    pub(crate) exclusive: bool,
+    pub(crate) init_chunk_size: Option<usize>,
+    pub(crate) validate_before_wait: bool,
+}
+
+/// Builds a [`PidSet`] with non-default options.
+///
+/// # Examples
+///
+/// ```rust
+/// use pid_set::PidSet;
+///
+/// let pid = std::process::Command::new("true").spawn().unwrap().id();
+/// let mut pid_set = PidSet::builder().check_fd_limit(true).build([pid]);
+/// pid_set.wait_all().unwrap();
+/// ```
+#[derive(Default)]
+pub struct PidSetBuilder {
+    options: Options,
+    on_error: Option<ErrorHook>,
+}
+
+impl std::fmt::Debug for PidSetBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PidSetBuilder")
+            .field("options", &self.options)
+            .field("on_error", &self.on_error.is_some())
+            .finish()
+    }
+}
+
+impl PidSetBuilder {
+    /// Creates a builder with the default options, equivalent to [`PidSet::new`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks `RLIMIT_NOFILE` before opening the pidfds, so that a set that cannot
+    /// fit in the file descriptor limit fails up front with
+    /// `PidSetError::FdLimitExceeded` instead of midway with `EMFILE`.
+    ///
+    /// Disabled by default, since some callers raise the limit dynamically.
+    pub fn check_fd_limit(mut self, enabled: bool) -> Self {
+        self.options.check_fd_limit = enabled;
+        self
+    }
+
+    /// Overrides the `maxevents` passed to `epoll_wait`, see
+    /// [`PidSet::set_max_events`].
+    pub fn max_events(mut self, n: usize) -> Self {
+        self.options.max_events = Some(n);
+        self
+    }
+
+    /// Keeps the exit of every PID reported by a wait, queryable through
+    /// [`PidSet::exit_info`], instead of discarding it.
+    ///
+    /// The pidfd is still closed and deregistered; only the record is retained.
+    /// Records are only evicted by [`PidSet::take_exited`], so memory otherwise
+    /// grows with every exit over the lifetime of the set. Disabled by default.
+    pub fn retain_exited(mut self, enabled: bool) -> Self {
+        self.options.retain_exited = enabled;
+        self
+    }
+
+    /// Declares that children are reaped by the kernel, so exit statuses are never
+    /// queried and every [`ExitInfo`](crate::ExitInfo) has unknown `code` and
+    /// `signal`.
+    ///
+    /// When `SIGCHLD` is ignored or has `SA_NOCLDWAIT` set, the kernel reaps
+    /// children as soon as they exit. Their pidfd still reports the exit, but the
+    /// status is lost and `waitid` fails with `ECHILD`. This configuration is also
+    /// detected on each wait, the flag only makes it explicit and saves the check.
+    pub fn expect_autoreaped(mut self, enabled: bool) -> Self {
+        self.options.expect_autoreaped = enabled;
+        self
+    }
+
+    /// Registers the pidfds with `EPOLLONESHOT`, so that the kernel disarms each of
+    /// them after its first event and the `EPOLL_CTL_DEL` per exit is skipped.
+    ///
+    /// This only works because a process exit is a terminal, single event: once
+    /// reported, the pidfd is closed and never needs re-arming. Disabled by
+    /// default.
+    pub fn oneshot(mut self, enabled: bool) -> Self {
+        self.options.oneshot = enabled;
+        self
+    }
+
+    /// Registers the pidfds with `EPOLLEXCLUSIVE` (Linux 4.5+).
+    ///
+    /// This is only meaningful when the same pidfds are watched by several epoll
+    /// instances, e.g. worker processes sharing pidfds passed over a socket: the
+    /// kernel then wakes only one (or a few) of the instances blocked on an exit
+    /// instead of all of them. It is a wakeup hint, every instance still sees the
+    /// pidfd as readable on its next wait. `EPOLLEXCLUSIVE` cannot be combined with
+    /// [`PidSetBuilder::oneshot`] nor modified with `EPOLL_CTL_MOD`; registration
+    /// fails with `PidSetError::EpollCtl` (`EINVAL`) if it is. Disabled by default.
+    pub fn exclusive(mut self, enabled: bool) -> Self {
+        self.options.exclusive = enabled;
+        self
+    }
+
+    /// Opens at most `size` pidfds per call to [`PidSet::init_step`], so that a
+    /// large set can be initialized incrementally, e.g. by an async supervisor
+    /// yielding to its executor between steps.
+    ///
+    /// A smaller chunk bounds the time spent in each step, but takes more steps,
+    /// and so more total latency once interleaved with other work, to complete
+    /// the initialization. It does not affect [`PidSet::init`] nor the implicit
+    /// initialization on the first wait, which register every PID at once.
+    /// Unset by default: a single step then does the whole initialization. A
+    /// `size` of 0 is treated as 1.
+    pub fn init_chunk_size(mut self, size: usize) -> Self {
+        self.options.init_chunk_size = Some(size);
+        self
+    }
+
+    /// Runs [`PidSet::validate`] before each blocking wait, failing it with
+    /// `PidSetError::InvalidPidFds` instead of waiting forever for exits that a
+    /// closed pidfd will never report.
+    ///
+    /// This catches misconfiguration early, e.g. code elsewhere in the process
+    /// closing descriptors it does not own. The check costs one `fcntl` per
+    /// pidfd before every blocking `epoll_wait`, and `poll`, so a full
+    /// [`PidSet::wait_all`] of `n` PIDs exiting one at a time makes in the order
+    /// of `n²` syscalls. Waits that do not block, e.g. [`PidSet::try_wait_any`],
+    /// are not checked. Disabled by default.
+    pub fn validate_before_wait(mut self, enabled: bool) -> Self {
+        self.options.validate_before_wait = enabled;
+        self
+    }
+
+    /// Retries `pidfd_open` up to `attempts` times when it fails with `ESRCH`,
+    /// sleeping `delay` before the first retry and doubling it each time.
+    ///
+    /// This only helps in the narrow window right after fork/exec, when a PID
+    /// registered extremely soon may not be visible yet; it is no substitute for
+    /// getting the pidfd at spawn time with `CLONE_PIDFD`. A PID that really does
+    /// not exist is only reported after all retries. By default `pidfd_open`
+    /// fails fast.
+    pub fn pidfd_open_retries(mut self, attempts: u32, delay: Duration) -> Self {
+        self.options.pidfd_retry = Some(Retry { attempts, delay });
+        self
+    }
+
+    /// Fails a wait with `PidSetError::PidNotFound` when `epoll_wait` reports a
+    /// token that does not map to a monitored PID, or with
+    /// `PidSetError::InvalidToken` when it maps to no PID at all.
+    ///
+    /// Such a token is usually benign, e.g. a stale readiness for a PID removed
+    /// through [`PidSet::take_pidfd`], so by default it is skipped and the rest of
+    /// the batch is processed.
+    pub fn strict_tokens(mut self, enabled: bool) -> Self {
+        self.options.strict_tokens = enabled;
+        self
+    }
+
+    /// Consults `f` at the error sites below instead of always propagating the
+    /// error, e.g. to log a single failed PID and keep monitoring the others.
+    ///
+    /// Returning [`ErrorAction::Abort`] propagates the error as without a hook,
+    /// the default. [`ErrorAction::Continue`] skips what failed:
+    /// - Registering a PID while initializing, on the first wait or through
+    ///   [`PidSet::init`]: its `pidfd_open` or `EPOLL_CTL_ADD` failure drops the
+    ///   PID, which is then listed by [`PidSet::failed_pids`], and the other PIDs
+    ///   are registered.
+    /// - A failed `epoll_wait`: the wakeup is treated as empty, so blocking waits
+    ///   retry. A hook continuing on every error can thus spin on a persistent
+    ///   failure; abort once it repeats. An `epoll_wait` interrupted by a signal
+    ///   (`EINTR`) is always retried, without consulting the hook.
+    /// - In a batch of events, an unknown token with
+    ///   [`PidSetBuilder::strict_tokens`], `PidSetError::UnexpectedEvents` and
+    ///   `PidSetError::FdError`: the event is skipped, the rest of the batch is
+    ///   processed.
+    ///
+    /// Every other error, e.g. failing to create the epoll instance, is
+    /// propagated without consulting the hook, except those recorded in
+    /// [`PidSet::exit_failures`]. The hook is not carried over by
+    /// [`PidSet::try_clone`].
+    pub fn on_error<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&PidSetError) -> ErrorAction + Send + 'static,
+    {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Creates the `PidSet` with the specified PIDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `pids` - An iterator over the PIDs to monitor.
+    pub fn build<P: IntoIterator<Item = PID>>(self, pids: P) -> PidSet {
+        self.build_with_hasher(pids, RandomState::new())
+    }
+
+    /// Creates the `PidSet` with the specified PIDs, using `hasher` for the
+    /// internal PID map, see [`PidSet::with_hasher`].
+    pub fn build_with_hasher<P: IntoIterator<Item = PID>, S: BuildHasher>(
+        self,
+        pids: P,
+        hasher: S,
+    ) -> PidSet<S> {
+        let mut pid_set = PidSet::with_options(pids, self.options, hasher);
+        pid_set.on_error = self.on_error;
+        pid_set
+    }
+}
@@ -0,0 +1,147 @@
+//! A minimal C API, enabled with the `ffi` feature.
+//!
+//! Cargo cannot make the crate type depend on a feature, so the C library is
+//! built explicitly, e.g. as a static library:
+//!
+//! ```sh
+//! cargo rustc --release --lib --features ffi --crate-type staticlib
+//! cc -Iinclude examples/ffi/roundtrip.c target/release/libpid_set.a -o roundtrip
+//! ```
+//!
+//! The declarations are in `include/pid_set.h`.
+//!
+//! # Ownership
+//!
+//! - A set is created by [`pidset_new`] and owned by the caller, who must pass
+//!   it to [`pidset_close`] exactly once to free it. It must not be used after,
+//!   nor from several threads at the same time.
+//! - The set owns the epoll and pidfd file descriptors it opens, which stay
+//!   internal: they are closed when a PID exits or by [`pidset_close`], and must
+//!   never be closed by the caller.
+//! - PID arrays are only read during the call, and the set keeps its own copy.
+//!
+//! # Errors
+//!
+//! Functions returning an `int` return 0 on success and a negated `errno` value
+//! otherwise: the one of the failing syscall when there is one, or e.g.
+//! `-EINVAL` for a null set or an invalid PID and `-ENOENT` for a PID that is
+//! not monitored.
+
+use std::ffi::c_int;
+
+use crate::{PidSet, PidSetError, PID};
+
+/// Creates a set monitoring the `len` PIDs pointed to by `pids`.
+///
+/// The pidfds are opened lazily, by the first wait or [`pidset_add`]. Returns
+/// null if `pids` is null while `len` is not 0.
+///
+/// # Safety
+///
+/// `pids` must point to `len` readable PIDs, or may be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn pidset_new(pids: *const u32, len: usize) -> *mut PidSet {
+    let pids: &[PID] = match (pids.is_null(), len) {
+        (true, 0) => &[],
+        (true, _) => return std::ptr::null_mut(),
+        (false, _) => unsafe { std::slice::from_raw_parts(pids, len) },
+    };
+    Box::into_raw(Box::new(PidSet::new(pids.iter().copied())))
+}
+
+/// Starts monitoring `pid`, registering it right away.
+///
+/// # Safety
+///
+/// `set` must be null or a set returned by [`pidset_new`] and not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn pidset_add(set: *mut PidSet, pid: u32) -> c_int {
+    let Some(set) = (unsafe { set.as_mut() }) else {
+        return -libc::EINVAL;
+    };
+    result_code(set.insert(pid))
+}
+
+/// Blocks until one monitored PID exits and stores it in `pid`.
+///
+/// Returns `-ECHILD`, like `waitpid`, if the set has no PIDs left.
+///
+/// # Safety
+///
+/// `set` must be null or a set returned by [`pidset_new`] and not closed yet,
+/// and `pid` must be null or point to a writable `uint32_t`.
+#[no_mangle]
+pub unsafe extern "C" fn pidset_wait_any(set: *mut PidSet, pid: *mut u32) -> c_int {
+    let (Some(set), Some(pid)) = (unsafe { set.as_mut() }, unsafe { pid.as_mut() }) else {
+        return -libc::EINVAL;
+    };
+    match set.wait_n(1) {
+        Ok(exited) => match exited.first() {
+            Some(exited) => {
+                *pid = *exited;
+                0
+            }
+            None => -libc::ECHILD,
+        },
+        Err(err) => error_code(&err),
+    }
+}
+
+/// Closes every file descriptor of the set and frees it.
+///
+/// The set is freed even if closing fails. Closing a null set is a no-op.
+///
+/// # Safety
+///
+/// `set` must be null or a set returned by [`pidset_new`] and not closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn pidset_close(set: *mut PidSet) -> c_int {
+    if set.is_null() {
+        return 0;
+    }
+    let set = unsafe { Box::from_raw(set) };
+    result_code(set.close())
+}
+
+fn result_code(result: Result<(), PidSetError>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => error_code(&err),
+    }
+}
+
+/// Maps an error to a negated `errno`, the one of the failing syscall if any.
+fn error_code(err: &PidSetError) -> c_int {
+    let io_err = match err {
+        PidSetError::EpollCreate(err)
+        | PidSetError::EpollCtl(err)
+        | PidSetError::EpollWait(err)
+        | PidSetError::EpollClose(err)
+        | PidSetError::EventFd(err)
+        | PidSetError::SigMask(err)
+        | PidSetError::SignalFd(err)
+        | PidSetError::Cloexec(err)
+        | PidSetError::PidFdDup(err)
+        | PidSetError::PidFdInfo(err)
+        | PidSetError::Spawn(err)
+        | PidSetError::Poll(err)
+        | PidSetError::ProcChildren(err)
+        | PidSetError::GetRlimit(err)
+        | PidSetError::PidFdOpenSyscall(_, err)
+        | PidSetError::WaitPid(_, err)
+        | PidSetError::PidFdSendSignal(_, err) => err,
+        PidSetError::PidNotFound(_) => return -libc::ENOENT,
+        PidSetError::PidAlreadyMonitored(_) => return -libc::EEXIST,
+        PidSetError::FdLimitExceeded { .. } => return -libc::EMFILE,
+        PidSetError::UnexpectedEvents { .. }
+        | PidSetError::FdError(_)
+        | PidSetError::InvalidToken(_) => return -libc::EIO,
+        PidSetError::ProcUnavailable => return -libc::ENOSYS,
+        PidSetError::InvalidPidFds(_) => return -libc::EBADF,
+        PidSetError::InvalidPid(_)
+        | PidSetError::ThreadId { .. }
+        | PidSetError::PidMismatch { .. }
+        | PidSetError::InvalidHandoff(_) => return -libc::EINVAL,
+    };
+    -io_err.raw_os_error().unwrap_or(libc::EIO)
+}
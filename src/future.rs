@@ -0,0 +1,565 @@
+//! Futures over a [`PidSet`], usable with any executor.
+//!
+//! The crate has no async runtime dependency, so readiness is watched by helper
+//! threads instead:
+//! - [`JoinAll`] has a [`Watcher`] of the set's epoll instance, one per future
+//!   rather than one per PID, which wakes the task when exits are pending. The
+//!   exits themselves are processed by the task, on poll.
+//! - [`ExitFuture`]s of a set share a [`Reactor`], with an epoll instance of its
+//!   own, which dispatches each exit to the waker of the future awaiting it.
+//! - [`ExitStream`] and [`BatchedExitStream`] watch the set's epoll instance
+//!   like [`JoinAll`], yielding the exits as they are processed, and so does
+//!   [`WaitEmpty`], discarding them.
+
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    future::Future,
+    hash::BuildHasher,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    thread::JoinHandle,
+};
+
+use crate::{epoll_add, epoll_del, syserr, ExitInfo, Pid, PidFd, PidSet, PidSetError, FD, PID};
+
+/// Wakes the current waker whenever a file descriptor becomes readable.
+///
+/// Once woken, the watcher waits to be armed again by the next pending poll,
+/// since the descriptor stays readable until the task consumes its events.
+pub(crate) struct Watcher {
+    shared: Arc<Shared>,
+    /// An eventfd that interrupts the helper thread's `poll`.
+    cancel: FD,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    armed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    waker: Option<Waker>,
+    shutdown: bool,
+    /// Set by the helper thread before its last wakeup.
+    finished: bool,
+}
+
+impl Watcher {
+    /// Starts watching `fd`, which must stay open until the watcher is dropped.
+    pub(crate) fn spawn(fd: FD) -> Result<Self, PidSetError> {
+        let cancel =
+            unsafe { syserr(libc::eventfd(0, libc::EFD_CLOEXEC)) }.map_err(PidSetError::EventFd)?;
+        let shared = Arc::new(Shared::default());
+        let watched = shared.clone();
+        let thread = std::thread::Builder::new()
+            .name("pid-set-watcher".to_string())
+            .spawn(move || watch(fd, cancel, &watched))
+            .map_err(|err| {
+                unsafe { libc::close(cancel) };
+                PidSetError::Spawn(err)
+            })?;
+        Ok(Self {
+            shared,
+            cancel,
+            thread: Some(thread),
+        })
+    }
+
+    /// Wakes `waker` once the descriptor is readable, possibly right away.
+    pub(crate) fn arm(&self, waker: &Waker) {
+        let mut state = self.shared.state.lock().unwrap();
+        match &mut state.waker {
+            Some(armed) => armed.clone_from(waker),
+            armed => *armed = Some(waker.clone()),
+        }
+        self.shared.armed.notify_one();
+    }
+
+    /// Whether the helper thread has stopped watching, after a failed `poll` or
+    /// an error condition on the descriptor; arming it would never wake.
+    pub(crate) fn is_finished(&self) -> bool {
+        self.shared.state.lock().unwrap().finished
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.armed.notify_one();
+        let one: u64 = 1;
+        unsafe { libc::write(self.cancel, &one as *const u64 as *const libc::c_void, 8) };
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe { libc::close(self.cancel) };
+    }
+}
+
+/// The helper thread: waits to be armed, then for `fd` to be readable, and
+/// wakes the armed waker.
+fn watch(fd: FD, cancel: FD, shared: &Shared) {
+    loop {
+        {
+            let mut state = shared.state.lock().unwrap();
+            while state.waker.is_none() && !state.shutdown {
+                state = shared.armed.wait(state).unwrap();
+            }
+            if state.shutdown {
+                return;
+            }
+        }
+        let mut pollfds = [fd, cancel].map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        });
+        let res = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, -1) };
+        if res < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+            continue;
+        }
+        // cancelled, or a failure the task reports on its next wait
+        let finished = res < 0 || pollfds[1].revents != 0 || pollfds[0].revents & libc::POLLIN == 0;
+        let waker = {
+            let mut state = shared.state.lock().unwrap();
+            state.finished = finished;
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        if finished {
+            return;
+        }
+    }
+}
+
+/// The future of [`PidSet::join_all`].
+pub(crate) struct JoinAll<S> {
+    // dropped before the set, whose epoll instance it watches
+    watcher: Option<Watcher>,
+    set: PidSet<S>,
+    exits: Vec<ExitInfo>,
+}
+
+impl<S> JoinAll<S> {
+    pub(crate) fn new(set: PidSet<S>) -> Self {
+        Self {
+            watcher: None,
+            set,
+            exits: Vec::new(),
+        }
+    }
+}
+
+impl<S: BuildHasher> JoinAll<S> {
+    /// Collects the exits already pending, without blocking, and returns the
+    /// epoll instance to watch if some PIDs are left.
+    fn drain(&mut self) -> Result<Option<FD>, PidSetError> {
+        loop {
+            if self.set.is_empty() {
+                return Ok(None);
+            }
+            let epoll_fd = self.set.get_or_init_epoll()?;
+            let batch = self.set.wait_batch(epoll_fd, self.set.max_events(), 0)?;
+            if batch.is_empty() {
+                return Ok(Some(epoll_fd));
+            }
+            self.exits.extend(batch);
+        }
+    }
+}
+
+impl<S: BuildHasher> Future for JoinAll<S> {
+    type Output = Result<Vec<ExitInfo>, PidSetError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Some(epoll_fd) = this.drain()? else {
+            this.watcher = None;
+            return Poll::Ready(Ok(std::mem::take(&mut this.exits)));
+        };
+        let watcher = match this.watcher.take() {
+            Some(watcher) if !watcher.is_finished() => watcher,
+            _ => Watcher::spawn(epoll_fd)?,
+        };
+        // an exit in the meantime leaves the instance readable, waking right away
+        watcher.arm(cx.waker());
+        this.watcher = Some(watcher);
+        Poll::Pending
+    }
+}
+
+impl<S> Unpin for JoinAll<S> {}
+
+/// The future of [`PidSet::wait_empty`].
+pub(crate) struct WaitEmpty<'a, S> {
+    watcher: Option<Watcher>,
+    set: &'a mut PidSet<S>,
+}
+
+impl<'a, S> WaitEmpty<'a, S> {
+    pub(crate) fn new(set: &'a mut PidSet<S>) -> Self {
+        Self { watcher: None, set }
+    }
+}
+
+impl<S: BuildHasher> Future for WaitEmpty<'_, S> {
+    type Output = Result<(), PidSetError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let epoll_fd = loop {
+            if this.set.is_empty() {
+                this.watcher = None;
+                return Poll::Ready(Ok(()));
+            }
+            let epoll_fd = this.set.get_or_init_epoll()?;
+            let batch = this.set.wait_batch(epoll_fd, this.set.max_events(), 0)?;
+            if batch.is_empty() {
+                break epoll_fd;
+            }
+        };
+        let watcher = match this.watcher.take() {
+            Some(watcher) if !watcher.is_finished() => watcher,
+            _ => Watcher::spawn(epoll_fd)?,
+        };
+        watcher.arm(cx.waker());
+        this.watcher = Some(watcher);
+        Poll::Pending
+    }
+}
+
+impl<S> Unpin for WaitEmpty<'_, S> {}
+
+/// The state shared by [`ExitStream`] and [`BatchedExitStream`].
+struct Exits<S> {
+    // dropped before the set, whose epoll instance it watches
+    watcher: Option<Watcher>,
+    set: PidSet<S>,
+    failed: bool,
+}
+
+impl<S: BuildHasher> Exits<S> {
+    /// Yields the exits of a batch of up to `max_events` events, once there is
+    /// one with exits.
+    fn poll_batch(
+        &mut self,
+        cx: &mut Context<'_>,
+        max_events: usize,
+    ) -> Poll<Option<Result<Vec<ExitInfo>, PidSetError>>> {
+        if self.failed || self.set.is_empty() {
+            self.watcher = None;
+            return Poll::Ready(None);
+        }
+        match self.try_batch(max_events) {
+            Ok(Ok(batch)) => Poll::Ready(Some(Ok(batch))),
+            Ok(Err(epoll_fd)) => {
+                let watcher = match self.watcher.take() {
+                    Some(watcher) if !watcher.is_finished() => watcher,
+                    _ => match Watcher::spawn(epoll_fd) {
+                        Ok(watcher) => watcher,
+                        Err(err) => return self.fail(err),
+                    },
+                };
+                // an exit in the meantime leaves the instance readable, waking right away
+                watcher.arm(cx.waker());
+                self.watcher = Some(watcher);
+                Poll::Pending
+            }
+            Err(err) => self.fail(err),
+        }
+    }
+
+    /// Processes the pending events without blocking: the exits if there are
+    /// any, or else the epoll instance to watch.
+    fn try_batch(&mut self, max_events: usize) -> Result<Result<Vec<ExitInfo>, FD>, PidSetError> {
+        let epoll_fd = self.set.get_or_init_epoll()?;
+        let batch = self.set.wait_batch(epoll_fd, max_events, 0)?;
+        Ok(if batch.is_empty() {
+            Err(epoll_fd)
+        } else {
+            Ok(batch)
+        })
+    }
+
+    fn fail<T>(&mut self, err: PidSetError) -> Poll<Option<Result<T, PidSetError>>> {
+        self.failed = true;
+        self.watcher = None;
+        Poll::Ready(Some(Err(err)))
+    }
+}
+
+/// An async stream over exits, one at a time, created by
+/// [`PidSet::into_exit_stream`].
+///
+/// `std` has no `Stream` trait yet, so the stream is polled with the inherent
+/// [`ExitStream::poll_next`], which has the signature of the `Stream` trait of
+/// the `futures` crate and is meant to be forwarded to, or awaited item by item
+/// with [`ExitStream::next`]. It works with any executor.
+///
+/// The stream ends once every PID has been reported, or after the first error.
+/// Dropping it, even early, stops its helper thread and closes every file
+/// descriptor of the underlying set.
+pub struct ExitStream<S = RandomState> {
+    exits: Exits<S>,
+}
+
+impl<S: BuildHasher> ExitStream<S> {
+    pub(crate) fn new(set: PidSet<S>) -> Self {
+        Self {
+            exits: Exits {
+                watcher: None,
+                set,
+                failed: false,
+            },
+        }
+    }
+
+    /// Polls for the next exit, consuming exactly one PID of the set.
+    pub fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<ExitInfo, PidSetError>>> {
+        // a single event per wait, so at most one exit per batch
+        self.exits
+            .poll_batch(cx, 1)
+            .map(|item| item.map(|batch| batch.map(|mut batch| batch.remove(0))))
+    }
+
+    /// Waits for the next exit, `None` once the stream has ended.
+    pub async fn next(&mut self) -> Option<Result<ExitInfo, PidSetError>> {
+        std::future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+
+    /// Turns the stream into one yielding the exits of each batch together.
+    ///
+    /// The default, one exit per item, is what consumers of a stream usually
+    /// expect, and gives natural backpressure: each item is a separate wait
+    /// that consumes a single PID, so the exits a slow consumer has not taken
+    /// yet stay pending in the set, still counted by its length. In batches,
+    /// a single wait, and a single item, covers every exit ready at once, up to
+    /// the `maxevents` of [`PidSet::set_max_events`]: a burst of exits makes fewer, larger items and
+    /// fewer wakeups, but each batch leaves the set as a whole, and the handler
+    /// deals with a `Vec`.
+    pub fn batched(self) -> BatchedExitStream<S> {
+        BatchedExitStream { exits: self.exits }
+    }
+}
+
+impl<S> Unpin for ExitStream<S> {}
+
+/// An async stream over exits, each item holding the exits of one batch,
+/// created by [`ExitStream::batched`].
+///
+/// It is polled and ends like [`ExitStream`]. Items are never empty.
+pub struct BatchedExitStream<S = RandomState> {
+    exits: Exits<S>,
+}
+
+impl<S: BuildHasher> BatchedExitStream<S> {
+    /// Polls for the exits of the next batch with any.
+    pub fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Vec<ExitInfo>, PidSetError>>> {
+        let max_events = self.exits.set.max_events();
+        self.exits.poll_batch(cx, max_events)
+    }
+
+    /// Waits for the exits of the next batch, `None` once the stream has ended.
+    pub async fn next(&mut self) -> Option<Result<Vec<ExitInfo>, PidSetError>> {
+        std::future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl<S> Unpin for BatchedExitStream<S> {}
+
+/// The epoll token of the reactor's eventfd, which stops its thread.
+const SHUTDOWN_TOKEN: u64 = u64::MAX;
+
+/// Dispatches the exits of the pidfds it watches to the futures awaiting them.
+///
+/// It has its own epoll instance, since the events of the set's one are
+/// consumed by the set's waits, and watches its own duplicates of the pidfds,
+/// registered with `EPOLLONESHOT`: each reports one exit, to one future.
+pub(crate) struct Reactor {
+    registry: Arc<Mutex<Registry>>,
+    epoll_fd: FD,
+    shutdown: FD,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_key: u64,
+    entries: HashMap<u64, Entry>,
+}
+
+struct Entry {
+    fd: PidFd,
+    exited: bool,
+    waker: Option<Waker>,
+}
+
+impl Reactor {
+    pub(crate) fn new() -> Result<Self, PidSetError> {
+        let epoll_fd = unsafe { syserr(libc::epoll_create1(libc::EPOLL_CLOEXEC)) }
+            .map_err(PidSetError::EpollCreate)?;
+        let shutdown = match unsafe { syserr(libc::eventfd(0, libc::EFD_CLOEXEC)) } {
+            Ok(shutdown) => shutdown,
+            Err(err) => {
+                unsafe { libc::close(epoll_fd) };
+                return Err(PidSetError::EventFd(err));
+            }
+        };
+        let mut reactor = Self {
+            registry: Arc::default(),
+            epoll_fd,
+            shutdown,
+            thread: None,
+        };
+        epoll_add(epoll_fd, shutdown, SHUTDOWN_TOKEN, libc::EPOLLIN as u32)?;
+        let registry = reactor.registry.clone();
+        let thread = std::thread::Builder::new()
+            .name("pid-set-reactor".to_string())
+            .spawn(move || dispatch(epoll_fd, &registry))
+            .map_err(PidSetError::Spawn)?;
+        reactor.thread = Some(thread);
+        Ok(reactor)
+    }
+
+    /// Returns a future resolving with the exit of `pid`, watched through `fd`,
+    /// which the future owns from now on.
+    pub(crate) fn exit_future(
+        self: &Arc<Self>,
+        pid: PID,
+        fd: PidFd,
+    ) -> Result<ExitFuture, PidSetError> {
+        let mut registry = self.registry.lock().unwrap();
+        let key = registry.next_key;
+        let events = (libc::EPOLLIN | libc::EPOLLONESHOT) as u32;
+        if let Err(err) = epoll_add(self.epoll_fd, fd.0, key, events) {
+            fd.close();
+            return Err(err);
+        }
+        registry.next_key += 1;
+        registry.entries.insert(
+            key,
+            Entry {
+                fd,
+                exited: false,
+                waker: None,
+            },
+        );
+        Ok(ExitFuture {
+            reactor: self.clone(),
+            key,
+            pid,
+        })
+    }
+}
+
+impl Drop for Reactor {
+    fn drop(&mut self) {
+        let one: u64 = 1;
+        unsafe { libc::write(self.shutdown, &one as *const u64 as *const libc::c_void, 8) };
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        unsafe {
+            libc::close(self.shutdown);
+            libc::close(self.epoll_fd);
+        }
+    }
+}
+
+/// The reactor thread: marks the reported pidfds as exited and wakes their
+/// futures, until shut down.
+fn dispatch(epoll_fd: FD, registry: &Mutex<Registry>) {
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 16];
+    loop {
+        let res =
+            unsafe { libc::epoll_wait(epoll_fd, events.as_mut_ptr(), events.len() as i32, -1) };
+        if res < 0 {
+            if std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                continue;
+            }
+            return;
+        }
+        let mut wakers = Vec::new();
+        {
+            let mut registry = registry.lock().unwrap();
+            for event in &events[..res as usize] {
+                let key = event.u64;
+                if key == SHUTDOWN_TOKEN {
+                    return;
+                }
+                // the future may have been dropped since
+                if let Some(entry) = registry.entries.get_mut(&key) {
+                    entry.exited = true;
+                    wakers.extend(entry.waker.take());
+                }
+            }
+        }
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// The future of [`PidSet::exit_future`].
+pub struct ExitFuture {
+    reactor: Arc<Reactor>,
+    key: u64,
+    pid: PID,
+}
+
+impl Future for ExitFuture {
+    type Output = ExitInfo;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ExitInfo> {
+        let mut registry = self.reactor.registry.lock().unwrap();
+        let entry = registry
+            .entries
+            .get_mut(&self.key)
+            .expect("registered until dropped");
+        if !entry.exited {
+            match &mut entry.waker {
+                Some(waker) => waker.clone_from(cx.waker()),
+                waker => *waker = Some(cx.waker().clone()),
+            }
+            return Poll::Pending;
+        }
+        let fd = entry.fd;
+        drop(registry);
+        Poll::Ready(ExitInfo::from_pidfd(Pid(self.pid), fd).unwrap_or(ExitInfo::unknown(self.pid)))
+    }
+}
+
+impl Drop for ExitFuture {
+    fn drop(&mut self) {
+        let entry = self
+            .reactor
+            .registry
+            .lock()
+            .unwrap()
+            .entries
+            .remove(&self.key);
+        if let Some(entry) = entry {
+            let _ = epoll_del(self.reactor.epoll_fd, entry.fd);
+            entry.fd.close();
+        }
+    }
+}
+
+impl std::fmt::Debug for ExitFuture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExitFuture")
+            .field("pid", &self.pid)
+            .finish()
+    }
+}
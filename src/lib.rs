@@ -16,6 +16,11 @@
 //! pid_set = "0.1.0"
 //! ```
 //!
+//! ## Construction
+//! [`PidSet::new`] never fails: the pidfds are opened and registered lazily on the
+//! first wait. [`PidSet::try_new`] registers every PID immediately and returns any
+//! error at construction time instead.
+//!
 //! ## Examples
 //! Here's how you can use `PidSet` to monitor a list of PIDs:
 //!
@@ -39,242 +44,5327 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Children, orphans and subreapers
+//! Any process can be monitored, not only children of the caller: its exit is
+//! detected through its pidfd all the same. What differs is the exit status:
+//! - For a child, the status is read with `waitid`, without reaping it, and
+//!   [`ExitInfo::is_child`] is `true`. The zombie can later be reaped with
+//!   [`PidSet::reap`] or by its owner, e.g. a [`std::process::Child`].
+//! - For any other process, including an orphan reparented to init, only its
+//!   parent can reap it. The status is read with `PIDFD_GET_INFO` once the parent
+//!   did, which needs Linux 6.15+ and may not have happened yet when the exit is
+//!   reported; otherwise [`ExitInfo::code`] and [`ExitInfo::signal`] are `None`.
+//!   `reap` reports such a PID as not found.
+//!
+//! A process that became a subreaper with `prctl(PR_SET_CHILD_SUBREAPER)` adopts
+//! the orphans of its descendants, which are then its children in every
+//! respect. An orphan added before its parent exited is reported as a child
+//! only if it was adopted by the time it exits.
+//!
+//! ## Threads
+//! A pidfd refers to a whole process, i.e. a thread group: its exit is reported
+//! once every thread has exited, whether the main thread exits first or not, and
+//! never for the exit of an individual thread. Only the PID of a process, which
+//! is the thread ID of its main thread, can be monitored; the ID of another
+//! thread fails with `PidSetError::ThreadId`, naming the process it belongs to.
+//!
+//! ## `/proc`
+//! Monitoring itself only relies on syscalls: registering PIDs, waiting, and
+//! reading the exit status of children (`waitid`) or, on Linux 6.15+, of any
+//! process (`PIDFD_GET_INFO`). A few features read `/proc` instead, and fail
+//! with `PidSetError::ProcUnavailable` when it is not mounted, as in some minimal
+//! containers:
+//! - [`PidSet::all_children`], which discovers the children of the caller.
+//! - [`PidSet::add_pidfd`] without a PID, which reads it from the pidfd's fdinfo.
+//!
+//! `check_fd_limit` falls back to assuming only stdio is open. Use
+//! [`proc_available`] to pick a path up front.
+//!
+//! ## Platform support
+//! The crate relies on Linux-only syscalls (epoll, pidfd_open, signalfd). On any
+//! other target it compiles to an empty crate, so that cross-platform workspaces
+//! can depend on it and only fail to build where it is actually used.
+
+#![cfg(target_os = "linux")]
 
-use std::{collections::HashMap, usize};
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet, VecDeque},
+    hash::BuildHasher,
+    os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
+};
 
-use libc::{EPOLLIN, EPOLL_CTL_ADD, EPOLL_CTL_DEL};
+/// Compiles and runs the README examples as doctests.
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
+
+mod builder;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod future;
+mod pid_map;
+mod shared;
+mod signalfd;
+pub use builder::PidSetBuilder;
+pub use future::{BatchedExitStream, ExitFuture, ExitStream};
+pub use shared::SharedPidSet;
+pub use signalfd::SignalFdSet;
+
+use builder::{Options, Retry};
+use pid_map::PidMap;
+
+use libc::{EPOLLEXCLUSIVE, EPOLLIN, EPOLLONESHOT, EPOLL_CTL_ADD, EPOLL_CTL_DEL};
 
 type FD = i32;
+#[allow(clippy::upper_case_acronyms)]
 type PID = u32;
 
+/// A process ID, as opposed to any other integer handled by the syscall helpers.
+///
+/// The public API speaks plain `u32`, the type of [`std::process::Child::id`];
+/// PIDs are wrapped as soon as they reach the helpers below, so that passing a
+/// file descriptor or an epoll token where a PID is expected fails to compile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Pid(pub(crate) PID);
+
+impl Pid {
+    /// The epoll token a pidfd is registered with.
+    fn token(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// The PID a token was registered for, if it is one: `None` for the tokens
+    /// of the caller's descriptors, and for any value beyond the PID range that
+    /// a plain cast would truncate into some other PID.
+    fn from_token(token: u64) -> Option<Self> {
+        PID::try_from(token).ok().map(Self)
+    }
+}
+
+/// A pidfd, as opposed to an epoll or any other file descriptor.
+///
+/// It does not own the descriptor: pidfds are closed explicitly by the set that
+/// tracks them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PidFd(pub(crate) FD);
+
+impl PidFd {
+    /// Placeholder for a PID whose pidfd is opened lazily, on registration.
+    ///
+    /// Negative, since any other value can be a valid pidfd: `pidfd_open`
+    /// returns 0 if stdin was closed.
+    const UNOPENED: PidFd = PidFd(-1);
+
+    fn is_open(self) -> bool {
+        self.0 >= 0
+    }
+
+    fn close(self) {
+        if self.is_open() {
+            unsafe { libc::close(self.0) };
+        }
+    }
+}
+
+/// Set in the epoll token of the caller's file descriptors added with
+/// [`PidSet::add_fd`], which carry the descriptor in the low bits. PIDs are
+/// `u32`, so their tokens never have it.
+const FD_TOKEN_BIT: u64 = 1 << 63;
+
+/// The epoll token of a caller's file descriptor.
+fn fd_token(fd: FD) -> u64 {
+    // not sign-extended, a negative descriptor does not decode
+    FD_TOKEN_BIT | u64::from(fd as u32)
+}
+
+/// The caller's file descriptor a token was registered for, if it is one.
+fn fd_from_token(token: u64) -> Option<FD> {
+    if token & FD_TOKEN_BIT == 0 {
+        return None;
+    }
+    FD::try_from(token & !FD_TOKEN_BIT).ok()
+}
+
 /// A map of process IDs (PIDs) to their associated file descriptors.
-type FDPidsMap = HashMap<PID, FD>;
+type FDPidsMap<S = RandomState> = PidMap<S>;
+
+/// Maximum number of PIDs listed by [`PidSet::status_report`].
+const STATUS_REPORT_PIDS: usize = 10;
+
+/// Callback invoked for every PID removed by a wait, see [`PidSet::on_exit`].
+type ExitHook = Box<dyn FnMut(PID, Option<ExitInfo>) + Send>;
+
+/// Callback deciding what to do with a non-fatal error, see
+/// [`PidSetBuilder::on_error`].
+pub(crate) type ErrorHook = Box<dyn FnMut(&PidSetError) -> ErrorAction + Send>;
+
+/// Comparator ordering the exits of a batch, see [`PidSet::set_batch_order`].
+pub type BatchOrder = Box<dyn FnMut(PID, PID) -> std::cmp::Ordering + Send>;
 
 /// Manages a set of PIDs and their corresponding epoll file descriptors.
-pub struct PidSet {
-    fd_pids: FDPidsMap,
+///
+/// # Concurrency
+///
+/// A `PidSet` is [`Send`]: it can be moved to another thread, along with the
+/// file descriptors it owns, as [`PidSet::into_channel`] does. It is not
+/// [`Sync`], since the [`PidSet::on_exit`] hook only has to be `Send`, and every
+/// wait takes `&mut self` anyway: a single set must not be mutated from two
+/// threads without external synchronization, e.g. a `Mutex`. To drain a set of
+/// PIDs from several threads at once, use [`SharedPidSet`], which is `Sync`.
+///
+/// ```compile_fail
+/// fn assert_sync<T: Sync>() {}
+/// assert_sync::<pid_set::PidSet>();
+/// ```
+pub struct PidSet<S = RandomState> {
+    fd_pids: FDPidsMap<S>,
     epoll_fd: Option<FD>,
+    options: Options,
+    on_exit: Option<ExitHook>,
+    on_error: Option<ErrorHook>,
+    batch_order: Option<BatchOrder>,
+    exited_count: usize,
+    /// User correlation ids, kept aside so that the epoll token stays the PID.
+    tags: HashMap<PID, u64>,
+    priorities: HashMap<PID, i32>,
+    /// When each monitored PID was added to the set.
+    registered_at: HashMap<PID, Instant>,
+    /// PIDs dropped by `init_lenient`, until they are added again.
+    registration_failures: Vec<(PID, std::io::Error)>,
+    /// Errors hit while processing the exit of a PID, until taken.
+    exit_failures: Vec<(PID, PidSetError)>,
+    /// Terminal state of exited PIDs, only filled with `retain_exited`.
+    exit_records: HashMap<PID, ExitInfo>,
+    /// Caller's file descriptors added with `add_fd`, and their tokens.
+    user_fds: HashMap<FD, u64>,
+    /// Readiness of the caller's file descriptors not returned yet, by token.
+    fd_events: Vec<(u64, u32)>,
+    /// Scratch buffers reused by every wait, so that waiting does not allocate.
+    event_buf: Vec<libc::epoll_event>,
+    seen_buf: HashSet<PID>,
+    /// Signal mask applied for the duration of each wait, through `epoll_pwait`.
+    sigmask: Option<libc::sigset_t>,
+    /// Children reported as exited, which are zombies until reaped.
+    unreaped: HashSet<PID>,
+    #[cfg(feature = "stats")]
+    stats: Stats,
+    /// Dispatcher of the exit futures, started by the first one.
+    reactor: std::cell::OnceCell<std::sync::Arc<future::Reactor>>,
+}
+
+/// Counters of the `epoll_wait` calls made by a set, returned by
+/// [`PidSet::stats`] with the `stats` feature.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// How many times `epoll_wait` returned, including on timeouts.
+    pub waits: u64,
+    /// The time spent blocked in `epoll_wait`.
+    pub total_wait_time: Duration,
+    /// How many epoll events were reported, exits and [`PidSet::add_fd`]
+    /// readiness alike.
+    pub events: u64,
+}
+
+/// How a monitored process terminated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExitInfo {
+    pub pid: PID,
+    /// The exit code, if the process exited normally.
+    pub code: Option<i32>,
+    /// The signal that terminated the process, if it was killed.
+    pub signal: Option<i32>,
+    /// Whether the process is a child of the caller, whose status was collected
+    /// with `waitid` rather than observed from the outside.
+    ///
+    /// This is inferred from `waitid` succeeding on the pidfd instead of failing
+    /// with `ECHILD`. So it is `false` for children that were already reaped
+    /// (e.g. by [`std::process::Child::wait`] or because `SIGCHLD` is ignored),
+    /// and for the exits whose status was not queried at all. Children of any
+    /// thread of the process count as children. A `PidSet` leaves its children
+    /// waitable, while a [`SignalFdSet`] reaps them.
+    pub is_child: bool,
+}
+
+impl ExitInfo {
+    /// Reads the exit status of an exited process through its pidfd.
+    ///
+    /// Children are queried with `waitid`, leaving them waitable (`WNOWAIT`) so
+    /// that the caller can still reap it. For other processes, the status is read
+    /// with the `PIDFD_GET_INFO` ioctl (Linux 6.15+), which only reports it once
+    /// the process has been reaped by its parent.
+    ///
+    /// Returns `None` if the status is not available, e.g. on older kernels for a
+    /// process that is not a child of the caller, or for one not reaped yet.
+    fn from_pidfd(pid: Pid, fd: PidFd) -> Option<Self> {
+        Self::try_from_pidfd(pid, fd).ok().flatten()
+    }
+
+    /// Like [`ExitInfo::from_pidfd`], but fails if `waitid` does for another
+    /// reason than the process not being a child, and no fallback has the
+    /// status either.
+    fn try_from_pidfd(pid: Pid, fd: PidFd) -> std::io::Result<Option<Self>> {
+        match Self::from_waitid(pid, fd) {
+            Ok(Some(info)) => Ok(Some(info)),
+            Ok(None) => Ok(Self::from_pidfd_info(pid, fd)),
+            Err(err) => Self::from_pidfd_info(pid, fd).map(Some).ok_or(err),
+        }
+    }
+
+    fn from_waitid(Pid(pid): Pid, PidFd(fd): PidFd) -> std::io::Result<Option<Self>> {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let res = unsafe {
+            libc::waitid(
+                libc::P_PIDFD,
+                fd as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+            )
+        };
+        if res < 0 {
+            let err = std::io::Error::last_os_error();
+            // not a child, or already reaped
+            if err.raw_os_error() == Some(libc::ECHILD) {
+                return Ok(None);
+            }
+            return Err(err);
+        }
+        if unsafe { info.si_pid() } == 0 {
+            return Ok(None);
+        }
+        let status = unsafe { info.si_status() };
+        let (code, signal) = match info.si_code {
+            libc::CLD_EXITED => (Some(status), None),
+            libc::CLD_KILLED | libc::CLD_DUMPED => (None, Some(status)),
+            _ => return Ok(None),
+        };
+        Ok(Some(Self {
+            pid,
+            code,
+            signal,
+            is_child: true,
+        }))
+    }
+
+    fn from_pidfd_info(Pid(pid): Pid, PidFd(fd): PidFd) -> Option<Self> {
+        if !PIDFD_INFO_SUPPORTED.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut info = PidfdInfo {
+            mask: PIDFD_INFO_EXIT,
+            ..Default::default()
+        };
+        let res = unsafe { libc::ioctl(fd, PIDFD_GET_INFO, &mut info as *mut PidfdInfo) };
+        if res < 0 {
+            let err = std::io::Error::last_os_error().raw_os_error();
+            // ENOTTY before 6.13, the ioctl does not exist
+            if err == Some(libc::ENOTTY) || err == Some(libc::EINVAL) {
+                PIDFD_INFO_SUPPORTED.store(false, Ordering::Relaxed);
+            }
+            return None;
+        }
+        // not reaped yet, or a kernel without exit information (before 6.15)
+        if info.mask & PIDFD_INFO_EXIT == 0 {
+            return None;
+        }
+        Self::from_wait_status(pid, info.exit_code, false)
+    }
+
+    /// Decodes a `waitpid`-style status, `None` if the process did not terminate.
+    fn from_wait_status(pid: PID, status: libc::c_int, is_child: bool) -> Option<Self> {
+        let (code, signal) = if libc::WIFEXITED(status) {
+            (Some(libc::WEXITSTATUS(status)), None)
+        } else if libc::WIFSIGNALED(status) {
+            (None, Some(libc::WTERMSIG(status)))
+        } else {
+            return None;
+        };
+        Some(Self {
+            pid,
+            code,
+            signal,
+            is_child,
+        })
+    }
+
+    /// An exit whose status could not be retrieved.
+    fn unknown(pid: PID) -> Self {
+        Self {
+            pid,
+            code: None,
+            signal: None,
+            is_child: false,
+        }
+    }
+
+    /// Returns whether the process exited with code 0.
+    ///
+    /// Any nonzero code or terminating signal is a failure, and so is an exit
+    /// whose status is unknown, since its success cannot be confirmed.
+    pub fn success(&self) -> bool {
+        self.code == Some(0) && self.signal.is_none()
+    }
+
+    /// Returns the name of the terminating signal, e.g. `"SIGKILL"`.
+    pub fn signal_name(&self) -> Option<&'static str> {
+        self.signal.and_then(signal_name)
+    }
+}
+
+/// A consuming iterator over exits, created by [`PidSet::into_exit_iter`].
+///
+/// Each call to `next` blocks until a PID exits. Iteration ends once every PID
+/// has been reported, or after the first error. Dropping the iterator, even
+/// early, closes all file descriptors of the underlying set.
+pub struct ExitIter<S = RandomState> {
+    pid_set: PidSet<S>,
+    pending: VecDeque<PID>,
+    failed: bool,
+}
+
+impl<S: BuildHasher> Iterator for ExitIter<S> {
+    type Item = Result<PID, PidSetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            if self.failed || self.pid_set.is_empty() {
+                return None;
+            }
+            let batch = self.pid_set.get_or_init_epoll().and_then(|epoll_fd| {
+                let max_events = self.pid_set.max_events();
+                self.pid_set.wait_batch(epoll_fd, max_events, -1)
+            });
+            match batch {
+                Ok(batch) => self.pending.extend(batch.into_iter().map(|info| info.pid)),
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Which PIDs [`PidSet::init_lenient`] managed to register.
+#[derive(Debug)]
+pub struct RegistrationReport {
+    /// The PIDs now monitored.
+    pub registered: Vec<PID>,
+    /// The PIDs whose pidfd could not be opened, e.g. because they already
+    /// exited and were reaped, and which were removed from the set.
+    pub failed: Vec<(PID, std::io::Error)>,
+}
+
+/// The result of [`PidSet::wait`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaitResult {
+    /// The PIDs that exited during the wait, in the order they were reported.
+    pub exited: Vec<PID>,
+    /// The number of PIDs still monitored after the wait.
+    pub remaining: usize,
+}
+
+/// An event reported by [`PidSet::wait_events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PidSetEvent {
+    /// A monitored PID exited and was removed from the set.
+    Exited(ExitInfo),
+    /// A file descriptor added with [`PidSet::add_fd`] changed readiness.
+    Fd {
+        /// The token the descriptor was added with.
+        token: u64,
+        /// The epoll events reported, e.g. `EPOLLIN`, or `EPOLLHUP` and
+        /// `EPOLLRDHUP` once the write end of a pipe is closed.
+        events: u32,
+    },
+}
+
+/// The result of a wait bounded by a timeout.
+///
+/// A timeout is not an error: it is reported as [`WaitOutcome::TimedOut`], along
+/// with any PID that exited before it elapsed, since those were removed from the
+/// set all the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WaitOutcome {
+    /// All the requested PIDs exited before the timeout.
+    Exited(Vec<PID>),
+    /// The timeout elapsed first; holds the PIDs that exited in the meantime.
+    TimedOut(Vec<PID>),
+}
+
+/// What to do with an error passed to the [`PidSetBuilder::on_error`] hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Skip what failed and carry on, as documented for each error site.
+    Continue,
+    /// Return the error from the call, as without a hook.
+    Abort,
+}
+
+/// The result of [`PidSet::wait_any_or_fd`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptOutcome {
+    /// A monitored PID exited.
+    Exited(PID),
+    /// The interrupt file descriptor became readable first.
+    Interrupted,
+}
+
+/// Where a PID is in its lifecycle, as returned by [`PidSet::reap_state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReapState {
+    /// Monitored, not reported as exited yet.
+    Running,
+    /// A child reported as exited, which is a zombie until reaped.
+    Exited,
+    /// Reported as exited and no longer a zombie of this process.
+    Reaped,
+}
+
+/// Maps a signal number to its name, e.g. `9` to `"SIGKILL"`.
+///
+/// Returns `None` for unknown or out-of-range values.
+pub fn signal_name(sig: i32) -> Option<&'static str> {
+    let name = match sig {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGSTKFLT => "SIGSTKFLT",
+        libc::SIGCHLD => "SIGCHLD",
+        libc::SIGCONT => "SIGCONT",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        libc::SIGURG => "SIGURG",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGVTALRM => "SIGVTALRM",
+        libc::SIGPROF => "SIGPROF",
+        libc::SIGWINCH => "SIGWINCH",
+        libc::SIGIO => "SIGIO",
+        libc::SIGPWR => "SIGPWR",
+        libc::SIGSYS => "SIGSYS",
+        _ => return None,
+    };
+    Some(name)
 }
 
 /// Errors that can occur in the `PidSet`.
 #[derive(Debug, thiserror::Error)]
 pub enum PidSetError {
-    #[error("Error while creating epoll file instance:`{0}`")]
+    #[error("Error on epoll_create1: `{0}`")]
     EpollCreate(std::io::Error),
 
-    #[error("Error on pidfd_open syscall for pid `{0}`: `{1}")]
+    #[error("Error on pidfd_open for pid `{0}`: `{1}`")]
     PidFdOpenSyscall(u32, std::io::Error),
 
-    #[error("Error on epoll_ctl: `{0}")]
+    #[error("Error on epoll_ctl: `{0}`")]
     EpollCtl(std::io::Error),
 
-    #[error("Error on epoll_wait: `{0}")]
+    #[error("Error on epoll_wait: `{0}`")]
     EpollWait(std::io::Error),
 
-    #[error("PID not found: `{0}")]
+    #[error("PID not found: `{0}`")]
     PidNotFound(u32),
 
-    #[error("Error while closing epoll file descriptor: `{0}")]
+    #[error("PID `{tid}` is a thread of process `{tgid}`, only whole processes can be monitored")]
+    ThreadId { tid: u32, tgid: u32 },
+
+    #[error("PID mismatch, the pidfd monitored as `{pid}` refers to `{actual}`")]
+    PidMismatch { pid: u32, actual: u32 },
+
+    #[error("Unexpected epoll events `{events:#x}` without EPOLLIN for pid `{pid}`")]
+    UnexpectedEvents { pid: u32, events: u32 },
+
+    #[error("Error condition, EPOLLERR or EPOLLHUP, on the pidfd of pid `{0}`")]
+    FdError(u32),
+
+    #[error("Invalid epoll token `{0:#x}`, registered for neither a PID nor a file descriptor")]
+    InvalidToken(u64),
+
+    #[error("Invalid pidfds, their exits would never be reported: {}", list_invalid(.0))]
+    InvalidPidFds(Vec<(u32, std::io::Error)>),
+
+    #[error("PID already monitored: `{0}`")]
+    PidAlreadyMonitored(u32),
+
+    #[error("Invalid PID, out of the `pid_t` range: `{0}`")]
+    InvalidPid(u32),
+
+    #[error("Error on close of the epoll file descriptor: `{0}`")]
     EpollClose(std::io::Error),
+
+    #[error("Error on eventfd: `{0}`")]
+    EventFd(std::io::Error),
+
+    #[error("Error on pthread_sigmask while blocking SIGCHLD: `{0}`")]
+    SigMask(std::io::Error),
+
+    #[error("Error on signalfd: `{0}`")]
+    SignalFd(std::io::Error),
+
+    #[error("Error on waitpid for pid `{0}`: `{1}`")]
+    WaitPid(u32, std::io::Error),
+
+    #[error("Error on pidfd_send_signal for pid `{0}`: `{1}`")]
+    PidFdSendSignal(u32, std::io::Error),
+
+    #[error("Error on fcntl while setting FD_CLOEXEC: `{0}`")]
+    Cloexec(std::io::Error),
+
+    #[error("Invalid handoff of inherited file descriptors: `{0}`")]
+    InvalidHandoff(String),
+
+    #[error("Error on duplicating a pidfd: `{0}`")]
+    PidFdDup(std::io::Error),
+
+    #[error("Error on reading the pid of a pidfd: `{0}`")]
+    PidFdInfo(std::io::Error),
+
+    #[error("Error on spawning a command: `{0}`")]
+    Spawn(std::io::Error),
+
+    #[error("Error on poll: `{0}`")]
+    Poll(std::io::Error),
+
+    #[error("Error on accessing `/proc`, which is not mounted")]
+    ProcUnavailable,
+
+    #[error("Error on reading the children of the calling process: `{0}`")]
+    ProcChildren(std::io::Error),
+
+    #[error("Error on getrlimit: `{0}`")]
+    GetRlimit(std::io::Error),
+
+    #[error(
+        "Monitoring the set requires `{required}` file descriptors, but RLIMIT_NOFILE is `{limit}`"
+    )]
+    FdLimitExceeded { required: usize, limit: u64 },
+}
+
+/// Formats the PIDs of `PidSetError::InvalidPidFds`, each with its error.
+fn list_invalid(invalid: &[(PID, std::io::Error)]) -> String {
+    let invalid: Vec<String> = invalid
+        .iter()
+        .map(|(pid, err)| format!("`{pid}` (`{err}`)"))
+        .collect();
+    invalid.join(", ")
 }
 
 impl PidSet {
     /// Creates a new `PidSet` with the specified PIDs.
     ///
+    /// Construction is infallible: pidfds are opened lazily by the first call that
+    /// needs them, which is where registration errors are reported. Use
+    /// [`PidSet::try_new`] to register eagerly instead.
+    ///
     /// # Arguments
     ///
     /// * `pids` - An iterator over the PIDs to monitor.
     pub fn new<P: IntoIterator<Item = PID>>(pids: P) -> Self {
-        let fd_pids: FDPidsMap = pids.into_iter().map(|pid| (pid, 0)).collect();
-        Self {
-            fd_pids,
-            epoll_fd: None,
-        }
+        PidSet::with_options(pids, Options::default(), RandomState::new())
     }
 
-    fn register_pid(epoll_fd: i32, pid: u32, token: u32) -> Result<FD, PidSetError> {
-        let cfd = unsafe { syscallerr(libc::syscall(libc::SYS_pidfd_open, pid, 0)) }
-            .map_err(|err| PidSetError::PidFdOpenSyscall(pid, err))?;
-        // use pid as token
-        unsafe {
-            syserr(libc::epoll_ctl(
-                epoll_fd,
-                EPOLL_CTL_ADD,
-                cfd as i32,
-                &mut libc::epoll_event {
-                    events: EPOLLIN as u32,
-                    u64: token as u64,
-                } as *mut _ as *mut libc::epoll_event,
-            ))
-        }
-        .map_err(PidSetError::EpollCtl)?;
-        Ok(cfd as i32)
+    /// Creates a new `PidSet` with the specified PIDs, each tagged with its
+    /// correlation id, e.g. a job id, as by [`PidSet::retag`].
+    ///
+    /// Like [`PidSet::new`], construction is infallible and the PIDs are
+    /// registered, and so validated, on the first wait. A PID listed more than
+    /// once is monitored once, with the last of its tags.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - An iterator over the PIDs to monitor and their tags.
+    pub fn with_tags<I: IntoIterator<Item = (PID, u64)>>(pairs: I) -> Self {
+        let tags: HashMap<PID, u64> = pairs.into_iter().collect();
+        let mut pid_set = PidSet::new(tags.keys().copied());
+        pid_set.tags = tags;
+        pid_set
     }
 
-    fn deregister_pid(epoll_fd: i32, fd: i32) -> Result<(), PidSetError> {
-        let _ = unsafe {
-            syserr(libc::epoll_ctl(
-                epoll_fd,
-                EPOLL_CTL_DEL,
-                fd,
-                std::ptr::null_mut(),
-            ))
+    /// Creates a new `PidSet` and registers every PID right away.
+    ///
+    /// Unlike [`PidSet::new`], which defers opening the pidfds until the first
+    /// wait, this reports registration failures (e.g. a PID that no longer exists)
+    /// at construction time. On error, nothing is left open.
+    ///
+    /// # Arguments
+    ///
+    /// * `pids` - An iterator over the PIDs to monitor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if the epoll instance cannot be created or any PID
+    /// cannot be registered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use pid_set::{PidSet, PidSetError};
+    ///
+    /// fn main() -> Result<(), PidSetError> {
+    ///     let pid = std::process::Command::new("true").spawn().unwrap().id();
+    ///     let mut pid_set = PidSet::try_new([pid])?;
+    ///     pid_set.wait_all()?;
+    ///     pid_set.close()
+    /// }
+    /// ```
+    pub fn try_new<P: IntoIterator<Item = PID>>(pids: P) -> Result<Self, PidSetError> {
+        let mut pid_set = PidSet::new(pids);
+        pid_set.init_epoll()?;
+        Ok(pid_set)
+    }
+
+    /// Creates a new `PidSet` monitoring every current child of the calling
+    /// process, and registers them right away.
+    ///
+    /// Children are found by scanning `/proc` for processes whose parent is the
+    /// caller, so this is a snapshot: children spawned afterwards are not
+    /// included and have to be added with [`PidSet::insert`] or
+    /// [`PidSet::spawn_and_monitor`]. A child reaped by another thread before it
+    /// is registered makes the call fail, as with [`PidSet::try_new`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::ProcUnavailable` if `/proc` is not mounted,
+    /// `PidSetError::ProcChildren` if it cannot be read, or
+    /// `PidSetError` if any child cannot be registered.
+    pub fn all_children() -> Result<Self, PidSetError> {
+        if !proc_available() {
+            return Err(PidSetError::ProcUnavailable);
         }
-        .map_err(PidSetError::EpollWait)?;
-        Ok(())
+        PidSet::try_new(children().map_err(PidSetError::ProcChildren)?)
     }
 
-    fn init_epoll(&mut self) -> Result<FD, PidSetError> {
-        // EPOLL_CLOEXEC flag disabled
-        let epoll_fd =
-            unsafe { syserr(libc::epoll_create1(0)) }.map_err(PidSetError::EpollCreate)?;
-        for (pid, fd) in &mut self.fd_pids {
-            *fd = PidSet::register_pid(epoll_fd, *pid, *pid)?;
+    /// Rebuilds a set from the file descriptors inherited across `execve`, as
+    /// described by a handoff produced by [`PidSet::export_for_exec`] before the
+    /// exec.
+    ///
+    /// Nothing is reopened: the epoll instance and its registrations survive the
+    /// exec along with the file descriptors, so exits that happened in between
+    /// are reported by the first wait. Close-on-exec is set again on the pidfds,
+    /// as for a fresh set, while the epoll descriptor is left without it, as it
+    /// is created. Options, tags and priorities are not part of the handoff and
+    /// start from the defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::InvalidHandoff` if `handoff` cannot be parsed or
+    /// names a file descriptor that is not open, e.g. because it was not
+    /// inherited, or `PidSetError::Cloexec` if close-on-exec cannot be set.
+    pub fn from_inherited(handoff: &str) -> Result<Self, PidSetError> {
+        let invalid = || PidSetError::InvalidHandoff(handoff.to_string());
+        let mut fields = handoff.split_whitespace();
+        let epoll_fd: FD = fields
+            .next()
+            .and_then(|fd| fd.parse().ok())
+            .ok_or_else(invalid)?;
+        let mut fd_pids = Vec::new();
+        for field in fields {
+            let (pid, fd) = field
+                .split_once(':')
+                .and_then(|(pid, fd)| Some((pid.parse::<PID>().ok()?, fd.parse::<FD>().ok()?)))
+                .ok_or_else(invalid)?;
+            fd_pids.push((pid, PidFd(fd)));
+        }
+        let is_open = |fd: FD| unsafe { libc::fcntl(fd, libc::F_GETFD) } >= 0;
+        if !is_open(epoll_fd) || !fd_pids.iter().all(|(_, fd)| is_open(fd.0)) {
+            return Err(invalid());
+        }
+        for (_, fd) in &fd_pids {
+            set_cloexec(fd.0, true).map_err(PidSetError::Cloexec)?;
         }
 
-        self.epoll_fd = Some(epoll_fd);
-        Ok(epoll_fd)
+        let mut pid_set = PidSet::new(std::iter::empty());
+        pid_set.fd_pids.extend(fd_pids);
+        let now = Instant::now();
+        pid_set.registered_at = pid_set.fd_pids.keys().map(|pid| (*pid, now)).collect();
+        pid_set.epoll_fd = Some(epoll_fd);
+        Ok(pid_set)
     }
-}
 
-fn syserr(status_code: libc::c_int) -> std::io::Result<libc::c_int> {
-    if status_code < 0 {
-        return Err(std::io::Error::from_raw_os_error(status_code));
+    /// Returns a [`PidSetBuilder`] to configure a `PidSet` with non-default options.
+    pub fn builder() -> PidSetBuilder {
+        PidSetBuilder::new()
     }
-    Ok(status_code)
 }
 
-fn syscallerr(status_code: libc::c_long) -> std::io::Result<libc::c_long> {
-    if status_code < 0 {
-        return Err(std::io::Error::last_os_error());
+impl<S: BuildHasher> PidSet<S> {
+    /// Creates a new `PidSet` with the specified PIDs, using `hasher` for the
+    /// internal PID map.
+    ///
+    /// PIDs are trusted integer keys, so a fast hasher without DoS resistance can
+    /// replace the default `RandomState` in high-churn supervisors. The map is
+    /// sized from the iterator's size hint, so a `HashMap`'s keys can be passed
+    /// without an intermediate collection.
+    ///
+    /// # Arguments
+    ///
+    /// * `pids` - An iterator over the PIDs to monitor.
+    /// * `hasher` - The hasher builder used by the PID map.
+    pub fn with_hasher<P: IntoIterator<Item = PID>>(pids: P, hasher: S) -> Self {
+        PidSet::with_options(pids, Options::default(), hasher)
     }
-    Ok(status_code)
-}
 
-impl PidSet {
-    pub fn insert(&mut self, pid: PID) -> Result<(), PidSetError> {
-        let epoll_fd = self.epoll_fd.unwrap_or(self.init_epoll()?);
-        PidSet::register_pid(epoll_fd, pid, pid)?;
+    /// Opens a pidfd for every PID without starting epoll monitoring.
+    ///
+    /// A pidfd pins its process: it keeps referring to it even if the PID is
+    /// reused later. Pinning early shrinks the window in which a PID can be
+    /// recycled, while the epoll instance is still created lazily by the first
+    /// wait, which registers the already open pidfds instead of reopening them.
+    /// PIDs that are already pinned or registered are left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if a pidfd cannot be opened, in which case the
+    /// pidfds opened by this call are closed again.
+    pub fn pin(&mut self) -> Result<(), PidSetError> {
+        let retry = self.options.pidfd_retry;
+        let mut opened: Vec<PID> = Vec::new();
+        let pinned = self.fd_pids.iter_mut().try_for_each(|(pid, fd)| {
+            if !fd.is_open() {
+                *fd = pidfd_open_retrying(Pid(*pid), retry)?;
+                opened.push(*pid);
+            }
+            Ok(())
+        });
+        if let Err(err) = pinned {
+            for pid in opened {
+                if let Some(fd) = self.fd_pids.get_mut(&pid) {
+                    fd.close();
+                    *fd = PidFd::UNOPENED;
+                }
+            }
+            return Err(err);
+        }
         Ok(())
     }
 
-    pub fn len(&self) -> usize {
-        self.fd_pids.len()
-    }
-}
-
-impl PidSet {
-    /// Waits for a specified number of PIDs to exit, up to the total number monitored.
+    /// Creates an independent set monitoring the same PIDs, e.g. for a second
+    /// consumer that wants its own notification of the same exits.
     ///
-    /// # Arguments
+    /// Every pidfd already open in this set is duplicated, so the clone refers
+    /// to the very same processes even if their PIDs are reused, while PIDs not
+    /// pinned yet stay lazily registered. The clone gets its own epoll instance
+    /// on its first wait; waiting on, closing or dropping either set does not
+    /// affect the other, since a pidfd can be watched by any number of epoll
+    /// instances.
     ///
-    /// * `n` - The number of PID events to wait for.
+    /// Options, tags, priorities and registration times are copied, while the
+    /// `on_exit` hook, the signal mask and the exit bookkeeping are not.
     ///
     /// # Errors
     ///
-    /// Returns `PidSetError` if an error occurs during epoll wait or if a PID is not found.
-    fn wait(&mut self, n: usize) -> Result<usize, PidSetError> {
-        let max_events = self.fd_pids.len();
-        let mut total_events: usize = 0;
-        let epoll_fd = self.epoll_fd.unwrap_or(self.init_epoll()?);
-        while total_events < n {
-            let mut events: Vec<libc::epoll_event> = Vec::with_capacity(max_events);
-            let event_count = syserr(unsafe {
-                libc::epoll_wait(epoll_fd, events.as_mut_ptr(), max_events as i32, -1)
-            })
-            .map_err(PidSetError::EpollWait)? as usize;
-            unsafe { events.set_len(event_count as usize) };
-            total_events += event_count;
+    /// Returns `PidSetError::PidFdDup` if a pidfd cannot be duplicated, in which
+    /// case the pidfds duplicated so far are closed again.
+    pub fn try_clone(&self) -> Result<Self, PidSetError>
+    where
+        S: Clone,
+    {
+        let mut clone = PidSet::with_options(
+            std::iter::empty(),
+            self.options.clone(),
+            self.fd_pids.hasher().clone(),
+        );
+        clone.fd_pids.reserve(self.fd_pids.len());
+        for (pid, fd) in self.fd_pids.iter() {
+            let fd = if fd.is_open() {
+                // a partially built clone closes its pidfds on drop
+                let dup = unsafe { syserr(libc::fcntl(fd.0, libc::F_DUPFD_CLOEXEC, 0)) }
+                    .map_err(PidSetError::PidFdDup)?;
+                PidFd(dup)
+            } else {
+                PidFd::UNOPENED
+            };
+            clone.fd_pids.insert(*pid, fd);
+        }
+        clone.tags = self.tags.clone();
+        clone.priorities = self.priorities.clone();
+        clone.registered_at = self.registered_at.clone();
+        Ok(clone)
+    }
 
-            for event in events {
-                let cdata = event.u64 as u32;
-                // TODO: return Error if event_count is -1
-                let fd = self
-                    .fd_pids
-                    .get(&cdata)
-                    .ok_or(PidSetError::PidNotFound(cdata))?;
-                PidSet::deregister_pid(epoll_fd, *fd)?;
+    /// Returns the PIDs dropped by [`PidSet::init_lenient`] because their pidfd
+    /// could not be opened, along with the error.
+    ///
+    /// Unlike the one-off [`RegistrationReport`], the record persists, so the
+    /// intended set can be reconciled against the monitored one at any time. A
+    /// PID leaves it once it is successfully added back, e.g. with
+    /// [`PidSet::insert`] or [`PidSet::add_pidfd`].
+    pub fn failed_pids(&self) -> &[(PID, std::io::Error)] {
+        &self.registration_failures
+    }
 
-                // remove from hashmap
-                self.fd_pids.remove(&cdata);
-            }
-        }
-        Ok(total_events)
+    /// Returns the errors hit while processing exits, along with the PID, in the
+    /// order they happened.
+    ///
+    /// Each exit in a batch is processed on its own, so that a failure for one
+    /// PID neither aborts the batch nor loses the others:
+    /// - `PidSetError::WaitPid` if the exit status could not be retrieved: the
+    ///   exit is reported all the same, with an unknown status. A process that
+    ///   is not a child of the caller has no status to retrieve, which is not a
+    ///   failure.
+    /// - `PidSetError::EpollCtl` if the exited pidfd could not be deregistered:
+    ///   it is closed, and the PID removed, all the same.
+    ///
+    /// Errors accumulate until taken with [`PidSet::take_exit_failures`].
+    pub fn exit_failures(&self) -> &[(PID, PidSetError)] {
+        &self.exit_failures
     }
 
-    /// Waits for all PIDs to exit.
+    /// Returns the errors listed by [`PidSet::exit_failures`] and clears them.
+    pub fn take_exit_failures(&mut self) -> Vec<(PID, PidSetError)> {
+        std::mem::take(&mut self.exit_failures)
+    }
+
+    /// Returns the PIDs currently monitored, in arbitrary order.
+    pub fn active_pids(&self) -> impl Iterator<Item = PID> + '_ {
+        self.fd_pids.keys().copied()
+    }
+
+    /// Creates the epoll instance and registers every PID now instead of on the
+    /// first wait.
+    ///
+    /// This separates setting up the monitoring from blocking on it, e.g. to check
+    /// up front that every PID exists. Calling it on an initialized set is a no-op.
     ///
     /// # Errors
     ///
-    /// Returns `PidSetError` if an error occurs during the wait.
-    pub fn wait_all(&mut self) -> Result<(), PidSetError> {
-        self.wait(self.fd_pids.len())?;
+    /// Returns `PidSetError` if the epoll instance cannot be created or a PID
+    /// cannot be registered, in which case the set stays uninitialized.
+    pub fn init(&mut self) -> Result<(), PidSetError> {
+        self.get_or_init_epoll()?;
         Ok(())
     }
 
-    /// Waits for any one PID to exit.
+    /// Performs one step of an incremental initialization and returns whether the
+    /// set is fully initialized.
+    ///
+    /// Each step opens the pidfds of up to [`PidSetBuilder::init_chunk_size`]
+    /// PIDs, pinning them as [`PidSet::pin`] does. The step that finds every PID
+    /// pinned creates the epoll instance and registers the pidfds, which only
+    /// takes an `epoll_ctl` per PID. Calling it on an initialized set is a no-op
+    /// returning `true`.
+    ///
+    /// ```rust
+    /// use pid_set::{PidSet, PidSetError};
+    ///
+    /// fn main() -> Result<(), PidSetError> {
+    ///     let pids = (0..8).map(|_| std::process::Command::new("true").spawn().unwrap().id());
+    ///     let mut pid_set = PidSet::builder().init_chunk_size(3).build(pids);
+    ///     while !pid_set.init_step()? {
+    ///         // interleave other work, e.g. yield to an async executor
+    ///     }
+    ///     pid_set.wait_all()?;
+    ///     pid_set.close()
+    /// }
+    /// ```
     ///
     /// # Errors
     ///
-    /// Returns `PidSetError` if an error occurs during the wait.
-    pub fn wait_any(&mut self) -> Result<(), PidSetError> {
-        self.wait(1)?;
-        Ok(())
+    /// Returns `PidSetError` if a pidfd cannot be opened, in which case the
+    /// pidfds opened by this step are closed again while those of earlier steps
+    /// stay pinned, or if the epoll instance cannot be created or a pidfd
+    /// registered, in which case the set stays uninitialized.
+    pub fn init_step(&mut self) -> Result<bool, PidSetError> {
+        if self.epoll_fd.is_some() {
+            return Ok(true);
+        }
+        let chunk = self.options.init_chunk_size.unwrap_or(usize::MAX).max(1);
+        let retry = self.options.pidfd_retry;
+        let mut opened: Vec<PID> = Vec::new();
+        let pinned = self
+            .fd_pids
+            .iter_mut()
+            .filter(|(_, fd)| !fd.is_open())
+            .take(chunk)
+            .try_for_each(|(pid, fd)| {
+                *fd = pidfd_open_retrying(Pid(*pid), retry)?;
+                opened.push(*pid);
+                Ok(())
+            });
+        if let Err(err) = pinned {
+            for pid in opened {
+                if let Some(fd) = self.fd_pids.get_mut(&pid) {
+                    fd.close();
+                    *fd = PidFd::UNOPENED;
+                }
+            }
+            return Err(err);
+        }
+        if self.fd_pids.values().any(|fd| !fd.is_open()) {
+            return Ok(false);
+        }
+        self.init_epoll()?;
+        Ok(true)
     }
 
-    /// Closes the epoll file descriptor and cleans up the `PidSet`.
+    /// Registers every PID it can, dropping the ones whose pidfd cannot be opened
+    /// instead of failing as a whole.
+    ///
+    /// Returns which PIDs are monitored and which failed, and why. If the set is
+    /// already initialized, nothing is registered and every PID is reported as
+    /// registered.
     ///
     /// # Errors
     ///
-    /// Returns `PidSetError` if an error occurs while closing the epoll file descriptor.
-    pub fn close(mut self) -> Result<(), PidSetError> {
-        let epoll_fd = self.epoll_fd.unwrap_or(self.init_epoll()?);
-        unsafe { syserr(libc::close(epoll_fd)) }.map_err(PidSetError::EpollClose)?;
-        Ok(())
+    /// Returns `PidSetError` if the epoll instance cannot be created or an opened
+    /// pidfd cannot be added to it.
+    pub fn init_lenient(&mut self) -> Result<RegistrationReport, PidSetError> {
+        let mut failed = Vec::new();
+        if self.epoll_fd.is_none() {
+            if self.options.check_fd_limit {
+                self.check_fd_limit()?;
+            }
+            let retry = self.options.pidfd_retry;
+            self.fd_pids.retain(|pid, fd| {
+                if fd.is_open() {
+                    return true;
+                }
+                match sys_pidfd_open_retrying(Pid(*pid), retry) {
+                    Ok(pidfd) => {
+                        *fd = pidfd;
+                        true
+                    }
+                    Err(err) => {
+                        failed.push((*pid, err));
+                        false
+                    }
+                }
+            });
+            for (pid, err) in &failed {
+                self.tags.remove(pid);
+                self.priorities.remove(pid);
+                self.registered_at.remove(pid);
+                self.registration_failures.push((*pid, copy_io_error(err)));
+            }
+            self.init_epoll()?;
+        }
+        Ok(RegistrationReport {
+            registered: self.fd_pids.keys().copied().collect(),
+            failed,
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::time::{Duration, Instant};
+    fn with_options<P: IntoIterator<Item = PID>>(pids: P, options: Options, hasher: S) -> Self {
+        let mut fd_pids = FDPidsMap::with_hasher(hasher);
+        fd_pids.extend(pids.into_iter().map(|pid| (pid, PidFd::UNOPENED)));
+        let now = Instant::now();
+        let registered_at = fd_pids.keys().map(|pid| (*pid, now)).collect();
+        Self {
+            fd_pids,
+            epoll_fd: None,
+            options,
+            on_exit: None,
+            on_error: None,
+            batch_order: None,
+            exited_count: 0,
+            #[cfg(feature = "stats")]
+            stats: Stats::default(),
+            reactor: std::cell::OnceCell::new(),
+            tags: HashMap::new(),
+            priorities: HashMap::new(),
+            registered_at,
+            registration_failures: Vec::new(),
+            exit_failures: Vec::new(),
+            exit_records: HashMap::new(),
+            user_fds: HashMap::new(),
+            fd_events: Vec::new(),
+            event_buf: Vec::new(),
+            seen_buf: HashSet::new(),
+            sigmask: None,
+            unreaped: HashSet::new(),
+        }
+    }
 
-    fn sleep_cmd(duration: &str) -> std::process::Command {
-        let mut cmd1 = std::process::Command::new("sleep");
-        cmd1.arg(duration);
-        cmd1
+    fn register_pid(&self, epoll_fd: FD, pid: Pid) -> Result<PidFd, PidSetError> {
+        let pidfd = pidfd_open_retrying(pid, self.options.pidfd_retry)?;
+        epoll_add_pidfd(epoll_fd, pidfd, pid, self.epoll_events())?;
+        Ok(pidfd)
     }
 
-    #[test]
-    fn wait_all() {
-        let mut pid_set = PidSet::new([
-            sleep_cmd("0.1").spawn().unwrap().id(),
-            sleep_cmd("0.2").spawn().unwrap().id(),
-            sleep_cmd("0.3").spawn().unwrap().id(),
-            sleep_cmd("0.4").spawn().unwrap().id(),
-            sleep_cmd("0.5").spawn().unwrap().id(),
+    /// Fails if registering every PID would exceed the `RLIMIT_NOFILE` soft limit.
+    fn check_fd_limit(&self) -> Result<(), PidSetError> {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        unsafe { syserr(libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim)) }
+            .map_err(PidSetError::GetRlimit)?;
+        // stdio is the bare minimum already open if /proc is unavailable
+        let in_use = std::fs::read_dir("/proc/self/fd").map_or(3, |dir| dir.count());
+        // one pidfd per PID plus the epoll instance
+        let required = in_use + self.fd_pids.len() + 1;
+        if rlim.rlim_cur != libc::RLIM_INFINITY && required as u64 > rlim.rlim_cur {
+            return Err(PidSetError::FdLimitExceeded {
+                required,
+                limit: rlim.rlim_cur,
+            });
+        }
+        Ok(())
+    }
+
+    fn init_epoll(&mut self) -> Result<FD, PidSetError> {
+        if self.options.check_fd_limit {
+            self.check_fd_limit()?;
+        }
+        // EPOLL_CLOEXEC flag disabled
+        let epoll_fd =
+            unsafe { syserr(libc::epoll_create1(0)) }.map_err(PidSetError::EpollCreate)?;
+        let events = self.epoll_events();
+        let retry = self.options.pidfd_retry;
+        // pidfds handed over through `add_pidfd` are already open
+        let mut opened: Vec<PID> = Vec::with_capacity(self.fd_pids.len());
+        let mut dropped: Vec<(PID, PidSetError)> = Vec::new();
+        let on_error = &mut self.on_error;
+        let registered = self.fd_pids.iter_mut().try_for_each(|(pid, fd)| {
+            let registered = (|| {
+                if !fd.is_open() {
+                    *fd = pidfd_open_retrying(Pid(*pid), retry)?;
+                    opened.push(*pid);
+                }
+                epoll_add_pidfd(epoll_fd, *fd, Pid(*pid), events)
+            })();
+            match registered {
+                Err(err) if consult(on_error, &err) == ErrorAction::Continue => {
+                    dropped.push((*pid, err));
+                    Ok(())
+                }
+                registered => registered,
+            }
+        });
+        if let Err(err) = registered {
+            // do not leave a partially registered set behind
+            for pid in opened {
+                if let Some(fd) = self.fd_pids.get_mut(&pid) {
+                    fd.close();
+                    *fd = PidFd::UNOPENED;
+                }
+            }
+            unsafe { libc::close(epoll_fd) };
+            return Err(err);
+        }
+        for (pid, err) in dropped {
+            if let Some(fd) = self.fd_pids.remove(&pid) {
+                fd.close();
+            }
+            self.tags.remove(&pid);
+            self.priorities.remove(&pid);
+            self.registered_at.remove(&pid);
+            if let PidSetError::PidFdOpenSyscall(_, err) | PidSetError::EpollCtl(err) = err {
+                self.registration_failures.push((pid, err));
+            }
+        }
+
+        self.epoll_fd = Some(epoll_fd);
+        Ok(epoll_fd)
+    }
+
+    /// The events every pidfd is registered with.
+    fn epoll_events(&self) -> u32 {
+        let mut events = EPOLLIN;
+        if self.options.oneshot {
+            events |= EPOLLONESHOT;
+        }
+        if self.options.exclusive {
+            events |= EPOLLEXCLUSIVE;
+        }
+        events as u32
+    }
+
+    fn get_or_init_epoll(&mut self) -> Result<FD, PidSetError> {
+        match self.epoll_fd {
+            Some(epoll_fd) => Ok(epoll_fd),
+            None => self.init_epoll(),
+        }
+    }
+}
+
+/// `PIDFD_GET_INFO`, `_IOWR(PIDFS_IOCTL_MAGIC, 11, struct pidfd_info)`.
+const PIDFD_GET_INFO: libc::Ioctl = 0xC040_FF0B_u32 as libc::Ioctl;
+/// Requests, and reports, the exit status in `pidfd_info`.
+const PIDFD_INFO_EXIT: u64 = 1 << 3;
+
+/// Cleared once `PIDFD_GET_INFO` turns out to be unsupported by the kernel.
+static PIDFD_INFO_SUPPORTED: AtomicBool = AtomicBool::new(true);
+
+/// The first version of `struct pidfd_info` (`PIDFD_INFO_SIZE_VER0`), not
+/// exposed by `libc` yet.
+#[repr(C)]
+#[derive(Default)]
+struct PidfdInfo {
+    mask: u64,
+    cgroupid: u64,
+    pid: u32,
+    tgid: u32,
+    ppid: u32,
+    ruid: u32,
+    rgid: u32,
+    euid: u32,
+    egid: u32,
+    suid: u32,
+    sgid: u32,
+    fsuid: u32,
+    fsgid: u32,
+    exit_code: i32,
+}
+
+/// Sets or clears `FD_CLOEXEC` on `fd`.
+fn set_cloexec(fd: FD, enabled: bool) -> std::io::Result<()> {
+    let flags = unsafe { syserr(libc::fcntl(fd, libc::F_GETFD)) }?;
+    let flags = if enabled {
+        flags | libc::FD_CLOEXEC
+    } else {
+        flags & !libc::FD_CLOEXEC
+    };
+    unsafe { syserr(libc::fcntl(fd, libc::F_SETFD, flags)) }?;
+    Ok(())
+}
+
+/// Returns whether procfs is mounted on `/proc`.
+///
+/// An empty `/proc` directory, as left by a container runtime that does not
+/// mount it, counts as unavailable.
+pub fn proc_available() -> bool {
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c"/proc".as_ptr(), &mut stat) } < 0 {
+        return false;
+    }
+    // both types depend on the target and libc
+    #[allow(clippy::unnecessary_cast)]
+    let is_proc = stat.f_type as i64 == libc::PROC_SUPER_MAGIC as i64;
+    is_proc
+}
+
+/// Reads the PID a pidfd refers to, failing if its process has been reaped.
+fn pidfd_pid(fd: FD) -> std::io::Result<PID> {
+    pidfd_current_pid(PidFd(fd))?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "the process of the pidfd has no PID in this namespace",
+        )
+    })
+}
+
+/// Reads the PID the process of a pidfd currently has, `None` once it has been
+/// reaped.
+fn pidfd_current_pid(PidFd(fd): PidFd) -> std::io::Result<Option<PID>> {
+    if PIDFD_INFO_SUPPORTED.load(Ordering::Relaxed) {
+        // the PID is always reported, whatever the mask
+        let mut info = PidfdInfo::default();
+        if unsafe { libc::ioctl(fd, PIDFD_GET_INFO, &mut info as *mut PidfdInfo) } == 0 {
+            return Ok(Some(info.pid));
+        }
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ESRCH) => return Ok(None),
+            Some(libc::ENOTTY | libc::EINVAL) => {
+                PIDFD_INFO_SUPPORTED.store(false, Ordering::Relaxed);
+            }
+            _ => return Err(err),
+        }
+    }
+    let fdinfo = std::fs::read_to_string(format!("/proc/self/fdinfo/{fd}"))?;
+    let pid = fdinfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Pid:"))
+        .and_then(|pid| pid.trim().parse::<i64>().ok())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "fdinfo has no valid Pid field",
+            )
+        })?;
+    // -1 once reaped, 0 if the process is in another PID namespace
+    Ok((pid > 0).then_some(pid as PID))
+}
+
+fn pidfd_open(pid: Pid) -> Result<PidFd, PidSetError> {
+    pidfd_open_retrying(pid, None)
+}
+
+fn pidfd_open_retrying(pid: Pid, retry: Option<Retry>) -> Result<PidFd, PidSetError> {
+    to_pid_t(pid)?;
+    sys_pidfd_open_retrying(pid, retry).map_err(|err| {
+        // pidfd_open refuses the ID of a thread that is not the group leader,
+        // with ENOENT since Linux 6.9 and EINVAL before
+        if matches!(err.raw_os_error(), Some(libc::ENOENT | libc::EINVAL)) {
+            if let Some(tgid) = thread_group_of(pid).filter(|tgid| *tgid != pid.0) {
+                return PidSetError::ThreadId { tid: pid.0, tgid };
+            }
+        }
+        PidSetError::PidFdOpenSyscall(pid.0, err)
+    })
+}
+
+/// Reads the process, i.e. thread group, that the thread `tid` belongs to from
+/// `/proc/<tid>/status`.
+fn thread_group_of(Pid(tid): Pid) -> Option<PID> {
+    let status = std::fs::read_to_string(format!("/proc/{tid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Tgid:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Opens a pidfd, retrying with exponential backoff while the PID is not
+/// visible yet (`ESRCH`).
+fn sys_pidfd_open_retrying(pid: Pid, retry: Option<Retry>) -> std::io::Result<PidFd> {
+    let Some(Retry {
+        attempts,
+        mut delay,
+    }) = retry
+    else {
+        return sys_pidfd_open(pid);
+    };
+    for _ in 0..attempts {
+        match sys_pidfd_open(pid) {
+            Err(err) if err.raw_os_error() == Some(libc::ESRCH) => {
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            res => return res,
+        }
+    }
+    sys_pidfd_open(pid)
+}
+
+/// Converts `pid` to the kernel's `pid_t`.
+///
+/// PIDs are kept as `u32`, the type of [`std::process::Child::id`], but only
+/// `1..=i32::MAX` name a single process: `0` and the values that would wrap to a
+/// negative `pid_t` designate process groups or the caller in most syscalls.
+fn to_pid_t(Pid(pid): Pid) -> Result<libc::pid_t, PidSetError> {
+    libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|pid| *pid > 0)
+        .ok_or(PidSetError::InvalidPid(pid))
+}
+
+fn sys_pidfd_open(pid: Pid) -> std::io::Result<PidFd> {
+    let pid =
+        to_pid_t(pid).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    let cfd = unsafe { syscallerr(libc::syscall(libc::SYS_pidfd_open, pid, 0)) }?;
+    Ok(PidFd(cfd as FD))
+}
+
+fn epoll_add(epoll_fd: FD, fd: FD, token: u64, events: u32) -> Result<(), PidSetError> {
+    unsafe {
+        syserr(libc::epoll_ctl(
+            epoll_fd,
+            EPOLL_CTL_ADD,
+            fd,
+            &mut libc::epoll_event { events, u64: token } as *mut _,
+        ))
+    }
+    .map_err(PidSetError::EpollCtl)?;
+    Ok(())
+}
+
+/// Registers `pidfd` in `epoll_fd`, with its PID as the token.
+fn epoll_add_pidfd(epoll_fd: FD, pidfd: PidFd, pid: Pid, events: u32) -> Result<(), PidSetError> {
+    epoll_add(epoll_fd, pidfd.0, pid.token(), events)
+}
+
+fn epoll_del(epoll_fd: FD, PidFd(fd): PidFd) -> Result<(), PidSetError> {
+    unsafe {
+        syserr(libc::epoll_ctl(
+            epoll_fd,
+            EPOLL_CTL_DEL,
+            fd,
+            std::ptr::null_mut(),
+        ))
+    }
+    .map_err(PidSetError::EpollCtl)?;
+    Ok(())
+}
+
+/// Sends `sig` to the process referred to by `fd`.
+///
+/// A process that already exited (`ESRCH`) is not an error: it is about to be
+/// reported by epoll anyway.
+fn pidfd_send_signal(Pid(pid): Pid, PidFd(fd): PidFd, sig: i32) -> Result<(), PidSetError> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            fd,
+            sig,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    };
+    match syscallerr(res) {
+        Err(err) if err.raw_os_error() != Some(libc::ESRCH) => {
+            Err(PidSetError::PidFdSendSignal(pid, err))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns whether the kernel reaps children automatically, because `SIGCHLD` is
+/// ignored or has `SA_NOCLDWAIT` set. Their exit status is then not retrievable.
+fn children_autoreaped() -> bool {
+    let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sigaction(libc::SIGCHLD, std::ptr::null(), &mut action) } < 0 {
+        return false;
+    }
+    action.sa_sigaction == libc::SIG_IGN || action.sa_flags & libc::SA_NOCLDWAIT != 0
+}
+
+/// Lists the children of the calling process from the parent PID field of every
+/// `/proc/<pid>/stat`.
+///
+/// `/proc/<pid>/task/<tid>/children` would be cheaper, but it requires
+/// `CONFIG_PROC_CHILDREN`, which many kernels are built without.
+fn children() -> std::io::Result<Vec<PID>> {
+    let parent = std::process::id();
+    let mut pids = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let Ok(pid) = entry?.file_name().to_string_lossy().parse::<PID>() else {
+            continue;
+        };
+        // the process may exit while iterating
+        let Ok(stat) = std::fs::read_to_string(format!("/proc/{pid}/stat")) else {
+            continue;
+        };
+        // the command name can contain spaces and parentheses, the fields
+        // after the last `)` are "state ppid ..."
+        let ppid = stat
+            .rsplit_once(')')
+            .and_then(|(_, fields)| fields.split_whitespace().nth(1))
+            .and_then(|ppid| ppid.parse::<PID>().ok());
+        if ppid == Some(parent) {
+            pids.push(pid);
+        }
+    }
+    Ok(pids)
+}
+
+/// Converts a deadline into an `epoll_wait` timeout in milliseconds, rounding up
+/// so that the wait never returns before the deadline.
+fn timeout_ms(deadline: Option<Instant>) -> i32 {
+    let Some(deadline) = deadline else {
+        return -1;
+    };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let millis = remaining.as_nanos().div_ceil(1_000_000);
+    millis.min(i32::MAX as u128) as i32
+}
+
+/// Asks the [`PidSetBuilder::on_error`] hook, if any, what to do with `err`.
+fn consult(on_error: &mut Option<ErrorHook>, err: &PidSetError) -> ErrorAction {
+    on_error
+        .as_mut()
+        .map_or(ErrorAction::Abort, |on_error| on_error(err))
+}
+
+/// Duplicates an `io::Error`, which is not `Clone`, keeping its errno or kind.
+fn copy_io_error(err: &std::io::Error) -> std::io::Error {
+    match err.raw_os_error() {
+        Some(errno) => std::io::Error::from_raw_os_error(errno),
+        None => std::io::Error::new(err.kind(), err.to_string()),
+    }
+}
+
+fn syserr(status_code: libc::c_int) -> std::io::Result<libc::c_int> {
+    if status_code < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(status_code)
+}
+
+fn syscallerr(status_code: libc::c_long) -> std::io::Result<libc::c_long> {
+    if status_code < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(status_code)
+}
+
+impl<S: BuildHasher> PidSet<S> {
+    pub fn insert(&mut self, pid: PID) -> Result<(), PidSetError> {
+        let epoll_fd = self.get_or_init_epoll()?;
+        let fd = self.register_pid(epoll_fd, Pid(pid))?;
+        if let Some(previous) = self.fd_pids.insert(pid, fd) {
+            epoll_del(epoll_fd, previous)?;
+            previous.close();
+        }
+        self.registered_at.insert(pid, Instant::now());
+        self.forget_failure(pid);
+        Ok(())
+    }
+
+    fn forget_failure(&mut self, pid: PID) {
+        self.registration_failures
+            .retain(|(failed, _)| *failed != pid);
+    }
+
+    /// Registers a hook invoked for every PID that exits, across all wait calls.
+    ///
+    /// The hook receives the exited PID and its exit status, see [`ExitInfo`] for
+    /// when it is available. It runs before the PID is removed from the set.
+    /// Registering a new hook replaces the previous one.
+    ///
+    /// If the hook panics, the panic propagates out of the wait without leaking
+    /// any file descriptor: every pidfd is owned by the set until it is closed, so
+    /// dropping the set during unwinding closes them all. The PID whose hook
+    /// panicked is left in the set and reported again by the next wait, while the
+    /// PIDs processed before it in the same batch are already removed.
+    pub fn on_exit(&mut self, f: Box<dyn FnMut(PID, Option<ExitInfo>) + Send>) {
+        self.on_exit = Some(f);
+    }
+
+    /// Monitors the process referred to by an already open pidfd.
+    ///
+    /// This is meant for processes spawned with `clone3(CLONE_PIDFD)` (or any
+    /// spawner exposing the pidfd it got at creation, such as the unstable
+    /// `std::os::linux::process::ChildExt::pidfd`). Unlike registering a PID, which
+    /// goes through `pidfd_open` and can race with the PID being reused, the pidfd
+    /// is guaranteed to refer to the intended process.
+    ///
+    /// The set takes ownership of `pidfd` and closes it once the process exits,
+    /// on `close`, or on drop. The PID is only used to label the process; when
+    /// `None`, it is read from `/proc/self/fdinfo`. A wrong `pid` is not detected
+    /// here, see [`PidSet::verify_pid`]. If the PID is already monitored, its
+    /// previous pidfd is replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::ProcUnavailable` if `pid` is `None` and `/proc` is not
+    /// mounted, `PidSetError::PidFdInfo` if the PID cannot be read from it, or
+    /// `PidSetError` if the pidfd cannot be registered.
+    pub fn add_pidfd(&mut self, pidfd: OwnedFd, pid: Option<PID>) -> Result<PID, PidSetError> {
+        let pid = match pid {
+            Some(pid) => pid,
+            None if !proc_available() => return Err(PidSetError::ProcUnavailable),
+            None => pidfd_pid(pidfd.as_raw_fd()).map_err(PidSetError::PidFdInfo)?,
+        };
+        if let Some(epoll_fd) = self.epoll_fd {
+            let events = self.epoll_events();
+            epoll_add_pidfd(epoll_fd, PidFd(pidfd.as_raw_fd()), Pid(pid), events)?;
+        }
+        if let Some(previous) = self.fd_pids.insert(pid, PidFd(pidfd.into_raw_fd())) {
+            if let Some(epoll_fd) = self.epoll_fd {
+                epoll_del(epoll_fd, previous)?;
+            }
+            previous.close();
+        }
+        self.registered_at.insert(pid, Instant::now());
+        self.forget_failure(pid);
+        Ok(pid)
+    }
+
+    /// Watches one of the caller's file descriptors, e.g. the read end of a
+    /// child's stdout pipe, in the same epoll instance as the pidfds.
+    ///
+    /// Its readiness is returned by [`PidSet::wait_events`] as
+    /// [`PidSetEvent::Fd`] with `token`, alongside the exits, so that a
+    /// supervisor can learn from one wait that the output of a child reached EOF
+    /// and that the child exited. The descriptor is registered edge-triggered for
+    /// `EPOLLIN` and `EPOLLRDHUP`: each change is reported once, and has to be
+    /// drained by the caller, e.g. by reading until `EAGAIN`, before it is
+    /// reported again. Readiness changes seen by the other waits are kept for
+    /// the next `wait_events`, coalesced by token.
+    ///
+    /// The set does not take ownership of `fd`: it is neither closed by the set
+    /// nor part of [`PidSet::len`], [`PidSet::try_clone`] or
+    /// [`PidSet::export_for_exec`]. Remove it with [`PidSet::remove_fd`] before
+    /// closing it. The set is initialized if it was not yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if the set cannot be initialized, or
+    /// `PidSetError::EpollCtl` if `fd` cannot be registered, e.g. because it is
+    /// already.
+    pub fn add_fd(&mut self, fd: RawFd, token: u64) -> Result<(), PidSetError> {
+        let epoll_fd = self.get_or_init_epoll()?;
+        let events = (EPOLLIN | libc::EPOLLRDHUP | libc::EPOLLET) as u32;
+        epoll_add(epoll_fd, fd, fd_token(fd), events)?;
+        self.user_fds.insert(fd, token);
+        Ok(())
+    }
+
+    /// Stops watching a file descriptor added with [`PidSet::add_fd`], dropping
+    /// its readiness not returned yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::EpollCtl` if `fd` is not watched.
+    pub fn remove_fd(&mut self, fd: RawFd) -> Result<(), PidSetError> {
+        let (Some(epoll_fd), Some(token)) = (self.epoll_fd, self.user_fds.get(&fd).copied()) else {
+            return Err(PidSetError::EpollCtl(std::io::Error::from_raw_os_error(
+                libc::ENOENT,
+            )));
+        };
+        epoll_del(epoll_fd, PidFd(fd))?;
+        self.user_fds.remove(&fd);
+        self.fd_events.retain(|(pending, _)| *pending != token);
+        Ok(())
+    }
+
+    /// Spawns `cmd` and starts monitoring the new child right away.
+    ///
+    /// The pidfd is opened before anyone can reap the child, so as long as nothing
+    /// else in the process reaps children (e.g. `waitpid(-1)` or `SIGCHLD` set to
+    /// `SIG_IGN`), the PID cannot be reused before it is pinned. Spawning through
+    /// `clone3(CLONE_PIDFD)` and [`PidSet::add_pidfd`] closes even that gap, but
+    /// is not exposed by `std::process::Command` on stable Rust.
+    ///
+    /// The `Child` handle is dropped, which closes any piped stdio but neither
+    /// kills nor reaps the process.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::Spawn` without touching the set if the command cannot
+    /// be spawned, or `PidSetError` if the pidfd cannot be opened or registered.
+    pub fn spawn_and_monitor(&mut self, cmd: &mut Command) -> Result<PID, PidSetError> {
+        let pid = cmd.spawn().map_err(PidSetError::Spawn)?.id();
+        let PidFd(pidfd) = pidfd_open_retrying(Pid(pid), self.options.pidfd_retry)?;
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd) };
+        self.add_pidfd(pidfd, Some(pid))
+    }
+
+    /// Stops monitoring `pid` and hands its pidfd over to the caller.
+    ///
+    /// The pidfd is deregistered from epoll (or freshly opened if the set has not
+    /// been initialized yet) and the PID is removed from the set, which no longer
+    /// tracks or closes that file descriptor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not monitored.
+    pub fn take_pidfd(&mut self, pid: PID) -> Result<OwnedFd, PidSetError> {
+        let fd = *self
+            .fd_pids
+            .get(&pid)
+            .ok_or(PidSetError::PidNotFound(pid))?;
+        let fd = match self.epoll_fd {
+            Some(epoll_fd) => {
+                epoll_del(epoll_fd, fd)?;
+                fd
+            }
+            None if fd.is_open() => fd,
+            None => pidfd_open_retrying(Pid(pid), self.options.pidfd_retry)?,
+        };
+        self.fd_pids.remove(&pid);
+        self.tags.remove(&pid);
+        self.priorities.remove(&pid);
+        self.registered_at.remove(&pid);
+        Ok(unsafe { OwnedFd::from_raw_fd(fd.0) })
+    }
+
+    /// Replaces the monitored PID `old` with `new`, e.g. after a process restart,
+    /// keeping the tag and priority of `old`.
+    ///
+    /// The pidfd of `new` is registered before the one of `old` is deregistered
+    /// and closed, so on error the set is left unchanged. If the set has not been
+    /// initialized yet, `new` is registered lazily like every other PID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `old` is not monitored, or
+    /// `PidSetError::PidAlreadyMonitored` if `new` already is.
+    pub fn replace_pid(&mut self, old: PID, new: PID) -> Result<(), PidSetError> {
+        let old_fd = *self
+            .fd_pids
+            .get(&old)
+            .ok_or(PidSetError::PidNotFound(old))?;
+        if self.fd_pids.contains_key(&new) {
+            return Err(PidSetError::PidAlreadyMonitored(new));
+        }
+        let new_fd = match self.epoll_fd {
+            Some(epoll_fd) => self.register_pid(epoll_fd, Pid(new))?,
+            None => PidFd::UNOPENED,
+        };
+        if let Some(epoll_fd) = self.epoll_fd {
+            if let Err(err) = epoll_del(epoll_fd, old_fd) {
+                // a registered `new` would report its exit as an unknown token
+                let _ = epoll_del(epoll_fd, new_fd);
+                new_fd.close();
+                return Err(err);
+            }
+        }
+        old_fd.close();
+        self.fd_pids.remove(&old);
+        self.fd_pids.insert(new, new_fd);
+        if let Some(tag) = self.tags.remove(&old) {
+            self.tags.insert(new, tag);
+        }
+        if let Some(priority) = self.priorities.remove(&old) {
+            self.priorities.insert(new, priority);
+        }
+        self.registered_at.remove(&old);
+        self.registered_at.insert(new, Instant::now());
+        self.forget_failure(new);
+        Ok(())
+    }
+
+    /// Prepares the set to be inherited across `execve` and returns the handoff
+    /// describing it, to be passed to [`PidSet::from_inherited`] in the new image,
+    /// e.g. through an environment variable.
+    ///
+    /// This is meant for supervisors re-executing themselves, for instance to
+    /// upgrade without losing track of their processes. The set is initialized
+    /// if needed and close-on-exec is cleared on the epoll and pidfd file
+    /// descriptors: pidfds are always opened with it, and an exec would close
+    /// them otherwise. The handoff is `"<epoll fd> <pid>:<pidfd> ..."`.
+    ///
+    /// Call it right before the exec: until then, any process spawned by the
+    /// supervisor inherits the file descriptors as well. If the exec fails, the
+    /// set is still usable, and exporting it again is harmless. A set must only
+    /// be rebuilt once from a handoff, since the rebuilt set owns and closes the
+    /// descriptors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if the set cannot be initialized, or
+    /// `PidSetError::Cloexec` if close-on-exec cannot be cleared.
+    pub fn export_for_exec(&mut self) -> Result<String, PidSetError> {
+        let epoll_fd = self.get_or_init_epoll()?;
+        set_cloexec(epoll_fd, false).map_err(PidSetError::Cloexec)?;
+        let mut handoff = epoll_fd.to_string();
+        for (pid, fd) in self.fd_pids.iter() {
+            set_cloexec(fd.0, false).map_err(PidSetError::Cloexec)?;
+            handoff.push_str(&format!(" {pid}:{}", fd.0));
+        }
+        Ok(handoff)
+    }
+
+    /// Checks that the pidfd monitored as `pid` refers to the process that
+    /// currently has that PID.
+    ///
+    /// A pidfd pins its process, so the PID a set tracks can only disagree with
+    /// it if it was mislabeled, e.g. by passing the wrong PID to
+    /// [`PidSet::add_pidfd`], or if a long-running supervisor re-adds a PID
+    /// number whose original process is gone. The PID is read with the
+    /// `PIDFD_GET_INFO` ioctl (Linux 6.13+), or from `/proc/self/fdinfo` on older
+    /// kernels.
+    ///
+    /// Once the process has been reaped, its PID can no longer be read and the
+    /// check passes: the pidfd still reports that very process exiting. So does a
+    /// PID whose pidfd is not opened yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not monitored,
+    /// `PidSetError::PidMismatch` if its pidfd refers to another PID, or
+    /// `PidSetError::PidFdInfo` if the PID of the pidfd cannot be read.
+    pub fn verify_pid(&self, pid: PID) -> Result<(), PidSetError> {
+        let fd = *self
+            .fd_pids
+            .get(&pid)
+            .ok_or(PidSetError::PidNotFound(pid))?;
+        if !fd.is_open() {
+            return Ok(());
+        }
+        match pidfd_current_pid(fd).map_err(PidSetError::PidFdInfo)? {
+            Some(actual) if actual != pid => Err(PidSetError::PidMismatch { pid, actual }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that every pidfd of the set is still an open file descriptor, and
+    /// returns the PIDs whose pidfd is not, along with the error.
+    ///
+    /// The set owns its pidfds, so an invalid one means that code elsewhere in
+    /// the process closed a descriptor it did not own, and the exit of that PID
+    /// will never be reported. Each pidfd is probed with a harmless
+    /// `fcntl(F_GETFD)`, leaving the set untouched. A closed descriptor whose
+    /// number was already reused for another file goes unnoticed; PIDs whose
+    /// pidfd is not opened yet are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the PIDs with an invalid pidfd, and why, if there is any.
+    pub fn validate(&self) -> Result<(), Vec<(PID, std::io::Error)>> {
+        let invalid: Vec<(PID, std::io::Error)> = self
+            .fd_pids
+            .iter()
+            .filter(|(_, fd)| fd.is_open())
+            .filter_map(|(pid, fd)| {
+                unsafe { syserr(libc::fcntl(fd.0, libc::F_GETFD)) }
+                    .err()
+                    .map(|err| (*pid, err))
+            })
+            .collect();
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid)
+        }
+    }
+
+    /// Fails with `PidSetError::InvalidPidFds` if
+    /// [`PidSetBuilder::validate_before_wait`] is enabled and a pidfd is invalid.
+    fn validate_before_wait(&self) -> Result<(), PidSetError> {
+        if !self.options.validate_before_wait {
+            return Ok(());
+        }
+        self.validate().map_err(PidSetError::InvalidPidFds)
+    }
+
+    /// Checks whether `pid` has exited without removing it from the set.
+    ///
+    /// The pidfd is probed with a zero-timeout `poll`, so repeated calls have no
+    /// side effects and the PID is still reported by later waits. An uninitialized
+    /// set stays so: only the pidfd of `pid` is opened, if it was not already,
+    /// and kept for the epoll instance created by the first wait.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not monitored, or
+    /// `PidSetError` if its pidfd cannot be opened.
+    pub fn has_exited(&mut self, pid: PID) -> Result<bool, PidSetError> {
+        let retry = self.options.pidfd_retry;
+        let Some(fd) = self.fd_pids.get_mut(&pid) else {
+            return Err(PidSetError::PidNotFound(pid));
+        };
+        if !fd.is_open() {
+            *fd = pidfd_open_retrying(Pid(pid), retry)?;
+        }
+        let mut pollfd = libc::pollfd {
+            fd: fd.0,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { syserr(libc::poll(&mut pollfd, 1, 0)) }.map_err(PidSetError::Poll)?;
+        Ok(ready > 0 && pollfd.revents & libc::POLLIN != 0)
+    }
+
+    /// Sets or updates the user tag (e.g. a job id) associated with `pid`.
+    ///
+    /// Tags live in a side table and the epoll registration is left untouched.
+    /// They are dropped when the PID leaves the set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not monitored.
+    pub fn retag(&mut self, pid: PID, new_tag: u64) -> Result<(), PidSetError> {
+        if !self.fd_pids.contains_key(&pid) {
+            return Err(PidSetError::PidNotFound(pid));
+        }
+        self.tags.insert(pid, new_tag);
+        Ok(())
+    }
+
+    /// Returns the user tag associated with `pid`, if any.
+    pub fn tag(&self, pid: PID) -> Option<u64> {
+        self.tags.get(&pid).copied()
+    }
+
+    /// Sets the priority of `pid`; the default is `0`.
+    ///
+    /// When several PIDs are reported by the same `epoll_wait` batch, they are
+    /// processed, passed to the [`PidSet::on_exit`] hook and returned in
+    /// decreasing priority order, so that a critical process is handled first
+    /// even amid a flood of exits. PIDs with equal priorities keep the arbitrary
+    /// order epoll reported them in. Priorities do not affect which PIDs end up
+    /// in a batch, only their order within it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not monitored.
+    pub fn set_priority(&mut self, pid: PID, priority: i32) -> Result<(), PidSetError> {
+        if !self.fd_pids.contains_key(&pid) {
+            return Err(PidSetError::PidNotFound(pid));
+        }
+        self.priorities.insert(pid, priority);
+        Ok(())
+    }
+
+    /// Returns the priority of `pid`, `0` unless set with [`PidSet::set_priority`].
+    pub fn priority(&self, pid: PID) -> i32 {
+        self.priorities.get(&pid).copied().unwrap_or(0)
+    }
+
+    /// Returns when `pid` was added to the set, if it is monitored.
+    ///
+    /// This is the time of construction, [`PidSet::insert`] or
+    /// [`PidSet::add_pidfd`], not of the lazy pidfd registration. A PID
+    /// substituted with [`PidSet::replace_pid`] starts afresh.
+    pub fn registered_at(&self, pid: PID) -> Option<Instant> {
+        self.registered_at.get(&pid).copied()
+    }
+
+    /// Returns for how long `pid` has been monitored, if it is.
+    ///
+    /// Scanning the ages of [`PidSet::active_pids`] is enough to enforce a
+    /// per-process run time limit, signaling the PIDs that exceed it.
+    pub fn age(&self, pid: PID) -> Option<Duration> {
+        self.registered_at(pid).map(|at| at.elapsed())
+    }
+
+    /// Sends `sig` to every monitored PID older than `max_age`, see
+    /// [`PidSet::age`], and returns the signaled PIDs.
+    ///
+    /// Meant to be called periodically by a supervisor enforcing a maximum run
+    /// time. The signal goes through the pidfd, opening it first if needed, so it
+    /// cannot hit another process that reused the PID. This does not wait for the
+    /// signaled processes to exit, they are reported by the next waits like any
+    /// other exit. A process that already exited is not an error and is
+    /// included in the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if a pidfd cannot be opened or the signal cannot be
+    /// sent, in which case the PIDs before it may already have been signaled.
+    pub fn enforce_deadline(
+        &mut self,
+        max_age: Duration,
+        sig: i32,
+    ) -> Result<Vec<PID>, PidSetError> {
+        let expired: Vec<PID> = self
+            .registered_at
+            .iter()
+            .filter(|(_, at)| at.elapsed() > max_age)
+            .map(|(pid, _)| *pid)
+            .collect();
+        let retry = self.options.pidfd_retry;
+        let mut signaled = Vec::with_capacity(expired.len());
+        for pid in expired {
+            let Some(fd) = self.fd_pids.get_mut(&pid) else {
+                continue;
+            };
+            if !fd.is_open() {
+                *fd = pidfd_open_retrying(Pid(pid), retry)?;
+            }
+            pidfd_send_signal(Pid(pid), *fd, sig)?;
+            signaled.push(pid);
+        }
+        Ok(signaled)
+    }
+
+    /// Returns the epoll file descriptor, initializing the set if needed.
+    ///
+    /// The descriptor becomes readable whenever at least one monitored pidfd is
+    /// ready, i.e. a process exited, so it can be driven by an external event loop
+    /// with `select`, `poll` or a parent epoll: add it to the interest set and call
+    /// [`PidSet::try_wait_any`] each time it is reported readable. The set keeps
+    /// ownership of the descriptor, which must not be closed by the caller.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if the set needs initializing and that fails.
+    pub fn epoll_fd(&mut self) -> Result<RawFd, PidSetError> {
+        self.get_or_init_epoll()
+    }
+
+    /// Overrides the `maxevents` passed to `epoll_wait`, which defaults to the
+    /// number of monitored PIDs.
+    ///
+    /// The value is clamped to at least 1 and at most the set size at the time of
+    /// each wait. A smaller value shrinks the per-call event buffer, at the cost of
+    /// more `epoll_wait` iterations when many processes exit at once.
+    pub fn set_max_events(&mut self, n: usize) {
+        self.options.max_events = Some(n);
+    }
+
+    /// Sets the signal mask to apply while blocked in a wait, or `None` to restore
+    /// the plain `epoll_wait` behavior.
+    ///
+    /// With a mask, waits call `epoll_pwait`, which atomically replaces the
+    /// thread's signal mask with `mask` for the duration of the wait only, like
+    /// `pselect`. This is the race-free way to have signals interrupt, or not, a
+    /// wait in a signal-aware event loop.
+    pub fn set_signal_mask(&mut self, mask: Option<libc::sigset_t>) {
+        self.sigmask = mask;
+    }
+
+    /// Sets a comparator ordering the PIDs that exit within the same
+    /// `epoll_wait` batch, or `None` to restore the default order.
+    ///
+    /// The batch is sorted before any of its exits is processed, so the
+    /// [`PidSet::on_exit`] hook and the returned PIDs follow that order. By
+    /// default, exits come in the arbitrary order epoll reported them, or by
+    /// decreasing [`PidSet::priority`] if any priority is set; the sort is
+    /// stable, so those orders break the ties of the comparator. Ordering only
+    /// applies within a single batch: a PID exiting later is reported by a later
+    /// batch, whatever the comparator says.
+    pub fn set_batch_order(&mut self, order: Option<BatchOrder>) {
+        self.batch_order = order;
+    }
+
+    fn max_events(&self) -> usize {
+        let len = self.fd_pids.len().max(1);
+        self.options.max_events.map_or(len, |n| n.clamp(1, len))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fd_pids.len()
+    }
+
+    /// Returns the number of PIDs that have been reported as exited by any wait
+    /// since the set was created.
+    ///
+    /// The counter is never reset: adding PIDs later does not change it, and PIDs
+    /// removed without exiting (e.g. through `take_pidfd`) are not counted.
+    pub fn exited(&self) -> usize {
+        self.exited_count
+    }
+
+    /// Returns the counters of the `epoll_wait` calls made since the set was
+    /// created or since the last [`PidSet::reset_stats`].
+    ///
+    /// Many more waits than events hint at spurious wakeups, and an average of
+    /// events per wait close to the batch size at a batch size worth raising,
+    /// see [`PidSet::set_max_events`]. The counters are never reset by the set
+    /// itself, and a [`PidSet::try_clone`] starts from zero. Failed calls are not
+    /// counted, and neither are the polls of [`PidSet::wait_any_or_fd`] and
+    /// [`PidSet::wait_any_with_ready`].
+    ///
+    /// Only available with the `stats` feature. It costs two clock reads per
+    /// `epoll_wait`, negligible next to the syscall itself but a waste for
+    /// callers that never look at the counters, hence a feature.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Resets the counters returned by [`PidSet::stats`], returning them, e.g. to
+    /// sample them over fixed intervals.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) -> Stats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Returns the recorded exit of `pid`, if it exited while the set was built
+    /// with [`PidSetBuilder::retain_exited`].
+    pub fn exit_info(&self, pid: PID) -> Option<&ExitInfo> {
+        self.exit_records.get(&pid)
+    }
+
+    /// Iterates over every recorded exit, in arbitrary order, e.g. to report how
+    /// each process finished once the set is drained.
+    ///
+    /// Only exits seen while the set was built with
+    /// [`PidSetBuilder::retain_exited`] are recorded, so the iterator is empty
+    /// otherwise. It borrows the records and does not allocate.
+    pub fn exited_iter(&self) -> impl Iterator<Item = (PID, &ExitInfo)> + '_ {
+        self.exit_records.iter().map(|(pid, info)| (*pid, info))
+    }
+
+    /// Returns every recorded exit and clears the records, in arbitrary order.
+    ///
+    /// With [`PidSetBuilder::retain_exited`], records accumulate for every exit;
+    /// harvesting them periodically, e.g. to ship them to a log, keeps memory
+    /// bounded. Only the records are affected: the monitored PIDs and the
+    /// [`PidSet::exited`] counter are untouched, and [`PidSet::exit_info`] no
+    /// longer knows the harvested PIDs.
+    ///
+    /// Taking `&mut self`, it cannot interleave with a wait: each exit is
+    /// returned by exactly one call. A harvesting thread other than the waiting
+    /// one needs the set behind a lock, e.g. a `Mutex`, held by the waits too.
+    pub fn take_exited(&mut self) -> Vec<(PID, ExitInfo)> {
+        self.exit_records.drain().collect()
+    }
+
+    /// Reaps `pid`, a child already reported as exited by a wait, and returns its
+    /// exit status.
+    ///
+    /// A wait reports a process as exited as soon as it terminates. If it is a
+    /// child of the caller, it then stays a zombie, holding its PID, until it is
+    /// reaped: the set leaves children waitable so that their owner, e.g. a
+    /// [`std::process::Child`], can still collect them, and this method lets the
+    /// caller do it instead, once it is done with the zombie. Non-children are
+    /// reaped by their own parent and are gone as soon as they are reported.
+    ///
+    /// Reaping never blocks, since the child is known to have exited.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not a child reported by a
+    /// wait and not reaped yet through this method, or `PidSetError::WaitPid` if
+    /// it was reaped by something else in the meantime.
+    pub fn reap(&mut self, pid: PID) -> Result<ExitInfo, PidSetError> {
+        if !self.unreaped.remove(&pid) {
+            return Err(PidSetError::PidNotFound(pid));
+        }
+        let mut status: libc::c_int = 0;
+        let res = unsafe { libc::waitpid(to_pid_t(Pid(pid))?, &mut status, libc::WNOHANG) };
+        if res < 0 {
+            return Err(PidSetError::WaitPid(pid, std::io::Error::last_os_error()));
+        }
+        // an exited child is always waitable
+        Ok(ExitInfo::from_wait_status(pid, status, true).unwrap_or(ExitInfo::unknown(pid)))
+    }
+
+    /// Returns where `pid` is in its lifecycle, e.g. to show the precise state of
+    /// each child in a supervisor UI.
+    ///
+    /// A monitored PID is [`ReapState::Running`] until a wait reports its exit.
+    /// A child then stays [`ReapState::Exited`], a zombie, until
+    /// [`PidSet::reap`] is called for it. Knowing that a PID was reaped, or that
+    /// a non-child exited, takes a record of its exit: with
+    /// [`PidSetBuilder::retain_exited`] it is [`ReapState::Reaped`], until the
+    /// record is taken by [`PidSet::take_exited`], and it is forgotten otherwise.
+    ///
+    /// Children reaped by the kernel, when `SIGCHLD` is ignored or with
+    /// [`PidSetBuilder::expect_autoreaped`], are gone by the time they are
+    /// reported: they skip [`ReapState::Exited`], and go straight to
+    /// [`ReapState::Reaped`] or are forgotten.
+    ///
+    /// A child reaped by something else than [`PidSet::reap`], e.g. its
+    /// [`std::process::Child`], is not noticed and stays `Exited`. Returns `None`
+    /// for PIDs the set does not know, or no longer remembers.
+    pub fn reap_state(&self, pid: PID) -> Option<ReapState> {
+        if self.fd_pids.contains_key(&pid) {
+            Some(ReapState::Running)
+        } else if self.unreaped.contains(&pid) {
+            Some(ReapState::Exited)
+        } else if self.exit_records.contains_key(&pid) {
+            Some(ReapState::Reaped)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of PIDs still monitored, same as [`PidSet::len`].
+    pub fn remaining(&self) -> usize {
+        self.fd_pids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fd_pids.is_empty()
+    }
+
+    /// Returns the `(PID, pidfd)` pairs currently registered with epoll, sorted by
+    /// PID, to debug a missing notification.
+    ///
+    /// This is a read-only snapshot of what the set believes it watches, to be
+    /// compared with the `tfd:` lines of `/proc/self/fdinfo/<epoll fd>`, which
+    /// list the descriptors the kernel actually watches. PIDs whose pidfd is not
+    /// open, or only pinned while the epoll instance does not exist yet, are left
+    /// out: nothing is registered for them. Descriptors added with
+    /// [`PidSet::add_fd`] are not PIDs and are not listed either. See also
+    /// [`PidSet::status_report`].
+    pub fn dump_interest(&self) -> Vec<(PID, RawFd)> {
+        if self.epoll_fd.is_none() {
+            return Vec::new();
+        }
+        let mut interest: Vec<(PID, RawFd)> = self
+            .fd_pids
+            .iter()
+            .filter(|(_, fd)| fd.is_open())
+            .map(|(pid, fd)| (*pid, fd.0))
+            .collect();
+        interest.sort_unstable();
+        interest
+    }
+
+    /// Returns a multi-line, human-readable summary of the set, meant for
+    /// operator-facing diagnostics.
+    ///
+    /// At most ten monitored PIDs are listed, the rest are summarized as
+    /// "... and N more".
+    pub fn status_report(&self) -> String {
+        use std::fmt::Write;
+
+        let open_fds = self.fd_pids.values().filter(|fd| fd.is_open()).count()
+            + usize::from(self.epoll_fd.is_some());
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        let limit = match unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } {
+            0 if rlim.rlim_cur != libc::RLIM_INFINITY => rlim.rlim_cur.to_string(),
+            0 => "unlimited".to_string(),
+            _ => "unknown".to_string(),
+        };
+
+        let mut report = String::new();
+        let _ = writeln!(report, "monitored: {}", self.fd_pids.len());
+        let _ = writeln!(report, "exited: {}", self.exited_count);
+        let _ = writeln!(
+            report,
+            "epoll: {}",
+            if self.epoll_fd.is_some() {
+                "initialized"
+            } else {
+                "not initialized"
+            }
+        );
+        let _ = writeln!(report, "fds: {open_fds} open (RLIMIT_NOFILE {limit})");
+
+        let mut pids: Vec<PID> = self
+            .fd_pids
+            .keys()
+            .take(STATUS_REPORT_PIDS)
+            .copied()
+            .collect();
+        pids.sort_unstable();
+        let listed: Vec<String> = pids.iter().map(PID::to_string).collect();
+        let _ = write!(report, "pids: {}", listed.join(", "));
+        let more = self.fd_pids.len() - pids.len();
+        if more > 0 {
+            let _ = write!(report, " ... and {more} more");
+        }
+        report.push('\n');
+        report
+    }
+}
+
+impl<S: BuildHasher> PidSet<S> {
+    /// Waits for at least `n` PIDs to exit, up to the total number monitored.
+    ///
+    /// Exits are consumed one `epoll_wait` batch at a time, so more than `n` PIDs
+    /// may be reported when several exit together. [`PidSet::wait_all`] and
+    /// [`PidSet::wait_any`] are shorthands for this call.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait(&mut self, n: usize) -> Result<WaitResult, PidSetError> {
+        let n = n.min(self.fd_pids.len());
+        let exited = self
+            .wait_until(n, None)?
+            .into_iter()
+            .map(|info| info.pid)
+            .collect();
+        Ok(WaitResult {
+            exited,
+            remaining: self.fd_pids.len(),
+        })
+    }
+
+    /// Waits until exactly `n` PIDs have exited and returns them, leaving the
+    /// others monitored for later waits.
+    ///
+    /// Unlike [`PidSet::wait`], no more than `n` exits are consumed, even when more
+    /// PIDs are ready: `epoll_wait` is asked for at most as many events as PIDs
+    /// still missing. `n` is capped at [`PidSet::len`], so the call returns early
+    /// with every PID if the set drains first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_n(&mut self, n: usize) -> Result<Vec<PID>, PidSetError> {
+        let mut exited = Vec::with_capacity(n.min(self.fd_pids.len()));
+        self.wait_into(n, &mut exited)?;
+        Ok(exited)
+    }
+
+    /// Like [`PidSet::wait_n`], but appends the exited PIDs to `out` and returns
+    /// how many were appended.
+    ///
+    /// The existing contents of `out` are kept, so clear it first to reuse it for
+    /// every wait. The set also reuses its own scratch buffers between waits, so
+    /// once they have grown to the largest batch, waiting only allocates when
+    /// `out` runs out of capacity or when recording an exit needs room: exits of
+    /// children for [`PidSet::reap`], and every exit with
+    /// [`PidSetBuilder::retain_exited`]. An `on_exit` hook may allocate as well.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait, in which case the
+    /// PIDs that exited before it are already appended.
+    pub fn wait_into(&mut self, n: usize, out: &mut Vec<PID>) -> Result<usize, PidSetError> {
+        self.wait_into_until(n, out, None)
+    }
+
+    /// Waits until exactly `n` PIDs have exited or `timeout` elapses, whichever
+    /// comes first, and returns the PIDs that exited.
+    ///
+    /// Like [`PidSet::wait_n`], no more than `n` exits are consumed and `n` is
+    /// capped at [`PidSet::len`]. The timeout bounds the whole call, not each
+    /// `epoll_wait`: every wakeup only waits for what is left of it. If it elapses
+    /// first, the PIDs that exited so far are returned, fewer than `n` and possibly
+    /// none; they are removed from the set all the same. Compare the length with
+    /// `n` to tell the two cases apart, or use [`PidSet::wait_deadline`] to get a
+    /// [`WaitOutcome`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_n_timeout(&mut self, n: usize, timeout: Duration) -> Result<Vec<PID>, PidSetError> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut exited = Vec::with_capacity(n.min(self.fd_pids.len()));
+        self.wait_into_until(n, &mut exited, deadline)?;
+        Ok(exited)
+    }
+
+    /// Waits for an exit, then keeps collecting exits for `window`, and returns
+    /// them all as one batch.
+    ///
+    /// This trades latency for throughput when many processes exit at nearly the
+    /// same time: a downstream handler gets e.g. "47 processes exited in the last
+    /// 100ms" as a single `Vec` instead of one notification per PID. The window
+    /// starts with the first exit, and the batch is returned as soon as it
+    /// closes, or earlier if the set drains. It is a deadline for the timed-wait
+    /// machinery of [`PidSet::wait_n_timeout`], so however many wakeups it
+    /// takes, the call returns no later than `window` after the first exit. A
+    /// zero window returns the first exit as soon as it is seen, like
+    /// [`PidSet::wait_any`].
+    ///
+    /// Returns an empty `Vec` right away if the set is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait, in which case the
+    /// exits already collected are lost for the caller but removed from the set.
+    pub fn wait_coalesced(&mut self, window: Duration) -> Result<Vec<PID>, PidSetError> {
+        let mut exited = Vec::new();
+        if self.wait_into(1, &mut exited)? == 0 {
+            return Ok(exited);
+        }
+        // a window too long to be represented stays open until the set drains
+        let deadline = Instant::now().checked_add(window);
+        self.wait_into_until(self.fd_pids.len(), &mut exited, deadline)?;
+        Ok(exited)
+    }
+
+    /// Appends up to `n` exited PIDs to `out`, giving up once `deadline` passes,
+    /// and returns how many were appended.
+    fn wait_into_until(
+        &mut self,
+        n: usize,
+        out: &mut Vec<PID>,
+        deadline: Option<Instant>,
+    ) -> Result<usize, PidSetError> {
+        let n = n.min(self.fd_pids.len());
+        if n == 0 {
+            return Ok(0);
+        }
+        let epoll_fd = self.get_or_init_epoll()?;
+        let mut exited = 0;
+        // An `on_error` hook that continues may drop PIDs, during init or a batch.
+        while exited < n && !self.fd_pids.is_empty() {
+            let max_events = self.max_events().min(n - exited);
+            let before = exited;
+            self.wait_batch_with(epoll_fd, max_events, timeout_ms(deadline), &mut |info| {
+                out.push(info.pid);
+                exited += 1;
+            })?;
+            if exited == before && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+        }
+        Ok(exited)
+    }
+
+    /// Waits for a specified number of PIDs to exit, up to the total number monitored.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of PID events to wait for.
+    /// * `deadline` - When to give up, returning the exits seen so far. `None`
+    ///   blocks until `n` PIDs have exited.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during epoll wait or if a PID is not found.
+    fn wait_until(
+        &mut self,
+        n: usize,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<ExitInfo>, PidSetError> {
+        let max_events = self.max_events();
+        let mut exits: Vec<ExitInfo> = Vec::with_capacity(n);
+        let epoll_fd = self.get_or_init_epoll()?;
+        // An `on_error` hook that continues may drop PIDs, during init or a batch.
+        while exits.len() < n && !self.fd_pids.is_empty() {
+            let batch = self.wait_batch(epoll_fd, max_events, timeout_ms(deadline))?;
+            if batch.is_empty() && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            exits.extend(batch);
+        }
+        Ok(exits)
+    }
+
+    /// Performs a single `epoll_wait` call and processes the reported batch.
+    ///
+    /// Returns the exits that were reported, which may be empty if `timeout` (in
+    /// milliseconds, `-1` to block) elapsed first.
+    fn wait_batch(
+        &mut self,
+        epoll_fd: FD,
+        max_events: usize,
+        timeout: i32,
+    ) -> Result<Vec<ExitInfo>, PidSetError> {
+        let mut exits = Vec::new();
+        self.wait_batch_with(epoll_fd, max_events, timeout, &mut |info| exits.push(info))?;
+        Ok(exits)
+    }
+
+    /// Like [`PidSet::wait_batch`], passing each exit to `report` instead of
+    /// collecting them. The event buffer is reused across calls.
+    fn wait_batch_with(
+        &mut self,
+        epoll_fd: FD,
+        max_events: usize,
+        timeout: i32,
+        report: &mut dyn FnMut(ExitInfo),
+    ) -> Result<(), PidSetError> {
+        if timeout != 0 {
+            self.validate_before_wait()?;
+        }
+        let mut events = std::mem::take(&mut self.event_buf);
+        events.clear();
+        events.reserve(max_events);
+        #[cfg(feature = "stats")]
+        let started = Instant::now();
+        let event_count = syserr(unsafe {
+            match &self.sigmask {
+                Some(mask) => libc::epoll_pwait(
+                    epoll_fd,
+                    events.as_mut_ptr(),
+                    max_events as i32,
+                    timeout,
+                    mask,
+                ),
+                None => libc::epoll_wait(epoll_fd, events.as_mut_ptr(), max_events as i32, timeout),
+            }
+        });
+        let event_count = match event_count {
+            Ok(event_count) => event_count as usize,
+            // an empty batch, which blocking waits retry with the time left
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+                self.event_buf = events;
+                return Ok(());
+            }
+            Err(err) => {
+                self.event_buf = events;
+                return self.tolerate(PidSetError::EpollWait(err));
+            }
+        };
+        #[cfg(feature = "stats")]
+        {
+            self.stats.waits += 1;
+            self.stats.total_wait_time += started.elapsed();
+            self.stats.events += event_count as u64;
+        }
+        unsafe { events.set_len(event_count) };
+        let processed = self.process_events_with(epoll_fd, &mut events, report);
+        self.event_buf = events;
+        processed
+    }
+
+    /// Collects the exits reported by [`PidSet::process_events_with`].
+    #[cfg(test)]
+    fn process_events(
+        &mut self,
+        epoll_fd: FD,
+        events: &mut [libc::epoll_event],
+    ) -> Result<Vec<ExitInfo>, PidSetError> {
+        let mut removed: Vec<ExitInfo> = Vec::with_capacity(events.len());
+        self.process_events_with(epoll_fd, events, &mut |info| removed.push(info))?;
+        Ok(removed)
+    }
+
+    /// Deregisters and removes every PID reported in a single `epoll_wait` batch.
+    ///
+    /// Level-triggered pidfds may report the same token more than once within a
+    /// batch; tokens already removed earlier in the batch are skipped rather than
+    /// reported as `PidNotFound`. Tokens that do not map to a monitored PID at all
+    /// are skipped as well, unless the set was built with
+    /// [`PidSetBuilder::strict_tokens`]. The exits of the PIDs removed are passed
+    /// to `report` in event order, or by decreasing priority if any was set, in
+    /// which case `events` is sorted in place.
+    ///
+    /// An event with `EPOLLERR`, or `EPOLLHUP` without `EPOLLIN`, for a pidfd is
+    /// an fd-level failure rather than an exit: the PID stops being monitored and
+    /// `PidSetError::FdError` is returned, the exits before it in the batch
+    /// having been reported already. Any other event without `EPOLLIN` is not an
+    /// exit either, and returns `PidSetError::UnexpectedEvents` the same way.
+    ///
+    /// Each exit is otherwise processed on its own: its status is retrieved by a
+    /// `waitid` on its own pidfd, and failing to retrieve it, or to deregister
+    /// the pidfd, is recorded in [`PidSet::exit_failures`] while the exit is
+    /// reported and the rest of the batch processed.
+    fn process_events_with(
+        &mut self,
+        epoll_fd: FD,
+        events: &mut [libc::epoll_event],
+        report: &mut dyn FnMut(ExitInfo),
+    ) -> Result<(), PidSetError> {
+        if !self.priorities.is_empty() && events.len() > 1 {
+            // stable, so equal priorities keep the event order
+            events.sort_by_key(|event| {
+                std::cmp::Reverse(Pid::from_token(event.u64).map_or(0, |pid| self.priority(pid.0)))
+            });
+        }
+        if let Some(order) = self.batch_order.as_mut().filter(|_| events.len() > 1) {
+            // the caller's descriptors go last, only PIDs are compared
+            events.sort_by(
+                |a, b| match (Pid::from_token(a.u64), Pid::from_token(b.u64)) {
+                    (Some(a), Some(b)) => order(a.0, b.0),
+                    (a, b) => a.is_none().cmp(&b.is_none()),
+                },
+            );
+        }
+        let mut seen = std::mem::take(&mut self.seen_buf);
+        seen.clear();
+        let processed = self.remove_reported(epoll_fd, events, &mut seen, report);
+        self.seen_buf = seen;
+        processed
+    }
+
+    fn remove_reported(
+        &mut self,
+        epoll_fd: FD,
+        events: &[libc::epoll_event],
+        seen: &mut HashSet<PID>,
+        report: &mut dyn FnMut(ExitInfo),
+    ) -> Result<(), PidSetError> {
+        // auto-reaped children are gone by the time they are reported
+        let skip_status =
+            !events.is_empty() && (self.options.expect_autoreaped || children_autoreaped());
+        for event in events {
+            if event.u64 & FD_TOKEN_BIT != 0 {
+                self.queue_fd_event(event);
+                continue;
+            }
+            let Some(Pid(cdata)) = Pid::from_token(event.u64) else {
+                // no registration of the set produces it
+                if self.options.strict_tokens {
+                    self.tolerate(PidSetError::InvalidToken(event.u64))?;
+                }
+                continue;
+            };
+            if !seen.insert(cdata) {
+                continue;
+            }
+            let Some(fd) = self.fd_pids.get(&cdata).copied() else {
+                if self.options.strict_tokens {
+                    self.tolerate(PidSetError::PidNotFound(cdata))?;
+                }
+                // stale token, e.g. for a PID whose pidfd was taken
+                continue;
+            };
+            // a pidfd reports its exit with EPOLLIN, along with EPOLLHUP once
+            // reaped; anything else is not an exit
+            let exited = event.events & EPOLLIN as u32 != 0;
+            if !exited || event.events & libc::EPOLLERR as u32 != 0 {
+                // level-triggered, it would be reported again by every wait
+                self.forget(epoll_fd, Pid(cdata), fd)?;
+                let err = if event.events & (libc::EPOLLERR | libc::EPOLLHUP) as u32 != 0 {
+                    PidSetError::FdError(cdata)
+                } else {
+                    PidSetError::UnexpectedEvents {
+                        pid: cdata,
+                        events: event.events,
+                    }
+                };
+                self.tolerate(err)?;
+                continue;
+            }
+            let info = if skip_status {
+                None
+            } else {
+                match ExitInfo::try_from_pidfd(Pid(cdata), fd) {
+                    Ok(info) => info,
+                    Err(err) => {
+                        self.exit_failures
+                            .push((cdata, PidSetError::WaitPid(cdata, err)));
+                        None
+                    }
+                }
+            };
+            if let Some(on_exit) = self.on_exit.as_mut() {
+                on_exit(cdata, info);
+            }
+            if let Err(err) = self.forget(epoll_fd, Pid(cdata), fd) {
+                self.exit_failures.push((cdata, err));
+            }
+            self.exited_count += 1;
+            let info = info.unwrap_or(ExitInfo::unknown(cdata));
+            if info.is_child {
+                self.unreaped.insert(cdata);
+            }
+            if self.options.retain_exited {
+                self.exit_records.insert(cdata, info);
+            }
+            report(info);
+        }
+        Ok(())
+    }
+
+    /// Waits for all PIDs to exit.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_all(&mut self) -> Result<(), PidSetError> {
+        self.wait(self.fd_pids.len())?;
+        Ok(())
+    }
+
+    /// Waits for all PIDs to exit, reporting progress after each batch of exits.
+    ///
+    /// `on_progress(exited_so_far, total)` is called after every `epoll_wait` batch
+    /// that removed at least one PID, where `total` is the number of PIDs monitored
+    /// when the call started.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_all_with_progress<F: FnMut(usize, usize)>(
+        &mut self,
+        mut on_progress: F,
+    ) -> Result<(), PidSetError> {
+        let total = self.fd_pids.len();
+        let mut exited = 0;
+        let epoll_fd = self.get_or_init_epoll()?;
+        while !self.fd_pids.is_empty() {
+            let batch = self.wait_batch(epoll_fd, self.max_events(), -1)?;
+            if !batch.is_empty() {
+                exited += batch.len();
+                on_progress(exited, total);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for any one PID to exit.
+    ///
+    /// Exactly one PID is consumed, as with `wait_n(1)`: if several PIDs are
+    /// ready at once, only one is removed from the set, and the others stay
+    /// monitored and are reported by the next waits. Returns right away if the
+    /// set is empty.
+    ///
+    /// When several PIDs are ready, the order in which they are reported is
+    /// arbitrary. No PID can starve, though: every reported PID is removed from
+    /// the set, and epoll moves the reported pidfds to the back of its ready
+    /// list, so the remaining ones are served by the next waits.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_any(&mut self) -> Result<(), PidSetError> {
+        self.wait_n(1)?;
+        Ok(())
+    }
+
+    /// Waits for one PID to exit and returns it, along with how many other PIDs
+    /// had exited as well by then.
+    ///
+    /// Exactly one PID is consumed: the others that are ready stay monitored and
+    /// are reported by the next waits, so the count gives visibility into bursts
+    /// of exits without any of them being lost. It is taken right after the wait
+    /// by polling the remaining pidfds, which has no effect on their epoll
+    /// registration, and costs one `poll` over the whole set.
+    ///
+    /// Returns `None` if the set is empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait or the poll.
+    pub fn wait_any_with_ready(&mut self) -> Result<Option<(PID, usize)>, PidSetError> {
+        let Some(pid) = self.wait_n(1)?.pop() else {
+            return Ok(None);
+        };
+        Ok(Some((pid, self.count_ready()?)))
+    }
+
+    /// Counts the monitored PIDs whose pidfd is readable, i.e. that have exited.
+    fn count_ready(&self) -> Result<usize, PidSetError> {
+        let mut pollfds: Vec<libc::pollfd> = self
+            .fd_pids
+            .values()
+            .filter(|fd| fd.is_open())
+            .map(|fd| libc::pollfd {
+                fd: fd.0,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        if pollfds.is_empty() {
+            return Ok(0);
+        }
+        unsafe {
+            syserr(libc::poll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                0,
+            ))
+        }
+        .map_err(PidSetError::Poll)?;
+        Ok(pollfds
+            .iter()
+            .filter(|pollfd| pollfd.revents & libc::POLLIN != 0)
+            .count())
+    }
+
+    /// Waits up to `timeout` for any one PID to exit.
+    ///
+    /// Returns [`WaitOutcome::Exited`] with the PIDs reported by the wakeup (at
+    /// least one), or [`WaitOutcome::TimedOut`] with no PIDs if none exited in time.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_any_timeout(&mut self, timeout: Duration) -> Result<WaitOutcome, PidSetError> {
+        self.wait_outcome(1, timeout)
+    }
+
+    /// Waits up to `timeout` for all PIDs to exit.
+    ///
+    /// Returns [`WaitOutcome::Exited`] if every PID exited in time, or
+    /// [`WaitOutcome::TimedOut`] with the PIDs that did otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_all_timeout(&mut self, timeout: Duration) -> Result<WaitOutcome, PidSetError> {
+        self.wait_outcome(self.fd_pids.len(), timeout)
+    }
+
+    /// Waits until at least `n` PIDs exit or `deadline` passes, and returns the
+    /// outcome along with the time left until the deadline.
+    ///
+    /// The leftover budget lets timed operations be chained without querying the
+    /// clock again, e.g. spending what remains of a shutdown grace period on the
+    /// next step. It is [`Duration::ZERO`] if the deadline was hit, i.e. whenever
+    /// the outcome is [`WaitOutcome::TimedOut`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_deadline(
+        &mut self,
+        n: usize,
+        deadline: Instant,
+    ) -> Result<(WaitOutcome, Duration), PidSetError> {
+        let outcome = self.wait_outcome_until(n.min(self.fd_pids.len()), Some(deadline))?;
+        let left = match outcome {
+            WaitOutcome::Exited(_) => deadline.saturating_duration_since(Instant::now()),
+            WaitOutcome::TimedOut(_) => Duration::ZERO,
+        };
+        Ok((outcome, left))
+    }
+
+    fn wait_outcome(&mut self, n: usize, timeout: Duration) -> Result<WaitOutcome, PidSetError> {
+        // a timeout too far out to be represented never elapses
+        self.wait_outcome_until(n, Instant::now().checked_add(timeout))
+    }
+
+    fn wait_outcome_until(
+        &mut self,
+        n: usize,
+        deadline: Option<Instant>,
+    ) -> Result<WaitOutcome, PidSetError> {
+        let exits = self.wait_until(n, deadline)?;
+        let timed_out = exits.len() < n && !self.fd_pids.is_empty();
+        let pids = exits.into_iter().map(|info| info.pid).collect();
+        Ok(if timed_out {
+            WaitOutcome::TimedOut(pids)
+        } else {
+            WaitOutcome::Exited(pids)
+        })
+    }
+
+    /// Waits up to `timeout` for all PIDs to exit, then sends `sig` to the
+    /// survivors and waits for them too.
+    ///
+    /// Returns the exit of every PID that was monitored. Survivors are signaled
+    /// through their pidfd, which keeps referring to the same process even if it
+    /// exits and its PID is reused in the meantime, unlike `kill(pid)`. If `sig`
+    /// does not terminate a survivor, this blocks until it exits.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait or while sending
+    /// the signal.
+    pub fn wait_all_or_kill(
+        &mut self,
+        timeout: Duration,
+        sig: i32,
+    ) -> Result<Vec<ExitInfo>, PidSetError> {
+        let mut exits = self.wait_until(self.fd_pids.len(), Instant::now().checked_add(timeout))?;
+        for (pid, fd) in self.fd_pids.iter() {
+            pidfd_send_signal(Pid(*pid), *fd, sig)?;
+        }
+        exits.extend(self.wait_until(self.fd_pids.len(), None)?);
+        Ok(exits)
+    }
+
+    /// Waits for all PIDs to exit and splits their exits into `(succeeded, failed)`.
+    ///
+    /// An exit succeeded if its code is 0 and it was not terminated by a signal;
+    /// any nonzero code, signal, or unknown status (the PID is not a child of the
+    /// caller) counts as failed. See [`ExitInfo::success`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_all_partitioned(&mut self) -> Result<(Vec<ExitInfo>, Vec<ExitInfo>), PidSetError> {
+        let exits = self.wait_until(self.fd_pids.len(), None)?;
+        Ok(exits.into_iter().partition(ExitInfo::success))
+    }
+
+    /// Waits for all PIDs to exit and returns their exits, in the order they were
+    /// reported.
+    ///
+    /// The set is left empty but initialized: the epoll instance stays open, so
+    /// PIDs added afterwards are registered with it and the set can be waited on
+    /// again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn drain_wait_all(&mut self) -> Result<Vec<ExitInfo>, PidSetError> {
+        self.get_or_init_epoll()?;
+        self.wait_until(self.fd_pids.len(), None)
+    }
+
+    /// Resolves once every monitored PID has exited, returning their exits in the
+    /// order they were reported; the async analog of [`PidSet::wait_all`].
+    ///
+    /// The future works with any executor. A single helper thread watches the
+    /// epoll instance of the whole set, and wakes the task when exits are
+    /// pending, which the task then processes like [`PidSet::try_wait_any`] does;
+    /// the `on_exit` hook thus runs in the task. The thread is only started if
+    /// the first poll finds PIDs still running.
+    ///
+    /// Dropping the future cancels the join: the helper thread is stopped and the
+    /// set is dropped, closing every file descriptor. The exits collected so far
+    /// are lost, as are the exits of a future that fails.
+    ///
+    /// # Errors
+    ///
+    /// Resolves to `PidSetError` if the set cannot be initialized, an error
+    /// occurs during a wait, or the helper thread cannot be started.
+    pub async fn join_all(self) -> Result<Vec<ExitInfo>, PidSetError> {
+        future::JoinAll::new(self).await
+    }
+
+    /// Waits, from async code, until every monitored PID has exited, e.g. as a
+    /// shutdown barrier.
+    ///
+    /// Unlike [`PidSet::join_all`], the set is borrowed rather than consumed,
+    /// and the exits are processed without being collected: the `on_exit`
+    /// hook, the records of [`PidSetBuilder::retain_exited`] and
+    /// [`PidSet::exited`] still see them. The set's epoll instance is watched by
+    /// a helper thread the same way, started only if the first poll finds PIDs
+    /// still running; an empty set resolves right away.
+    ///
+    /// Dropping the future stops the helper thread and leaves the set with the
+    /// PIDs not reported yet.
+    ///
+    /// # Errors
+    ///
+    /// Resolves to `PidSetError` if the set cannot be initialized, an error
+    /// occurs during a wait, or the helper thread cannot be started.
+    pub async fn wait_empty(&mut self) -> Result<(), PidSetError> {
+        future::WaitEmpty::new(self).await
+    }
+
+    /// Returns a future resolving with the exit of `pid`, e.g. to `select!` over
+    /// the exits of several processes in async code.
+    ///
+    /// The futures of a set are dispatched by a single helper thread, started by
+    /// the first one, with an epoll instance of its own watching a duplicate of
+    /// each awaited pidfd: awaiting an exit neither needs nor interferes with the
+    /// set's waits, and works with any executor. The set is left untouched, so
+    /// the exit is also reported by a wait on the set as usual, and any number of
+    /// futures may await the same PID.
+    ///
+    /// Dropping a future only stops that future: the PID is not deregistered from
+    /// the set, nor are other futures awaiting it affected. The futures outlive
+    /// the set if need be, and so does the helper thread, until the last one is
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::PidNotFound` if `pid` is not monitored, or
+    /// `PidSetError` if its pidfd cannot be duplicated, or opened if the set is
+    /// not initialized, or the helper thread cannot be started.
+    pub fn exit_future(&self, pid: PID) -> Result<ExitFuture, PidSetError> {
+        let Some(fd) = self.fd_pids.get(&pid).copied() else {
+            return Err(PidSetError::PidNotFound(pid));
+        };
+        let reactor = match self.reactor.get() {
+            Some(reactor) => reactor.clone(),
+            None => {
+                let reactor = std::sync::Arc::new(future::Reactor::new()?);
+                self.reactor.get_or_init(|| reactor).clone()
+            }
+        };
+        let fd = if fd.is_open() {
+            let dup = unsafe { syserr(libc::fcntl(fd.0, libc::F_DUPFD_CLOEXEC, 0)) }
+                .map_err(PidSetError::PidFdDup)?;
+            PidFd(dup)
+        } else {
+            pidfd_open_retrying(Pid(pid), self.options.pidfd_retry)?
+        };
+        reactor.exit_future(pid, fd)
+    }
+
+    /// Turns the set into an async stream yielding each exit, the async
+    /// counterpart of [`PidSet::into_exit_iter`].
+    ///
+    /// The set's epoll instance is watched by a helper thread, as for
+    /// [`PidSet::join_all`], started on the first poll that finds no exit
+    /// ready. Items come one exit at a time; [`ExitStream::batched`] turns the
+    /// stream into one yielding the exits of each batch together.
+    pub fn into_exit_stream(self) -> ExitStream<S> {
+        ExitStream::new(self)
+    }
+
+    /// Turns the set into an iterator yielding each PID as it exits.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use pid_set::{PidSet, PidSetError};
+    ///
+    /// fn main() -> Result<(), PidSetError> {
+    ///     let pids = (0..3).map(|_| std::process::Command::new("true").spawn().unwrap().id());
+    ///     let order: Vec<u32> = PidSet::new(pids).into_exit_iter().collect::<Result<_, _>>()?;
+    ///     assert_eq!(order.len(), 3);
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn into_exit_iter(self) -> ExitIter<S> {
+        ExitIter {
+            pid_set: self,
+            pending: VecDeque::new(),
+            failed: false,
+        }
+    }
+
+    /// Moves the set to a background thread that sends each PID, as it exits, to
+    /// the returned channel.
+    ///
+    /// The thread blocks on the set the same way [`PidSet::into_exit_iter`] does.
+    /// It sends at most one error, after which it stops, and it terminates once
+    /// every PID has exited. Dropping the receiver also stops it, although this is
+    /// only noticed at the next exit, since a blocked wait cannot be interrupted.
+    /// The epoll and pidfd file descriptors are owned by the thread and closed
+    /// when it terminates, and the `on_exit` hook, if any, runs on that thread.
+    pub fn into_channel(self) -> mpsc::Receiver<Result<PID, PidSetError>>
+    where
+        S: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for exit in self.into_exit_iter() {
+                if sender.send(exit).is_err() {
+                    break;
+                }
+            }
+        });
+        receiver
+    }
+
+    /// Checks, without blocking, whether any PID has exited.
+    ///
+    /// Returns `Some(pid)` for one exited PID, which is removed from the set just
+    /// like with [`PidSet::wait_any`], or `None` if no monitored process has exited
+    /// yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn try_wait_any(&mut self) -> Result<Option<PID>, PidSetError> {
+        if self.fd_pids.is_empty() {
+            return Ok(None);
+        }
+        let epoll_fd = self.get_or_init_epoll()?;
+        Ok(self
+            .wait_batch(epoll_fd, 1, 0)?
+            .into_iter()
+            .next()
+            .map(|info| info.pid))
+    }
+
+    /// Busy-polls for an exited PID with up to `spins` calls to
+    /// [`PidSet::try_wait_any`], returning `None` if none exited meanwhile.
+    ///
+    /// This is an explicit opt-in for latency-sensitive loops expecting an exit
+    /// imminently: it never sleeps, so it avoids the scheduler wakeup latency of
+    /// a blocking wait, at the cost of burning CPU and one zero-timeout
+    /// `epoll_wait` per spin. The blocking and timed waits should be preferred
+    /// otherwise. At least one check is made, even if `spins` is 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn try_wait_any_spin(&mut self, spins: u32) -> Result<Option<PID>, PidSetError> {
+        for _ in 0..spins.max(1) {
+            if let Some(pid) = self.try_wait_any()? {
+                return Ok(Some(pid));
+            }
+            std::hint::spin_loop();
+        }
+        Ok(None)
+    }
+
+    /// Waits until a monitored PID exits or `interrupt_fd` becomes readable,
+    /// whichever comes first.
+    ///
+    /// This composes process monitoring with any external wakeup source, e.g. a
+    /// timerfd, an eventfd shared with a shutdown handler or a socket, in a
+    /// single blocking wait. Returns [`InterruptOutcome::Exited`] with one PID,
+    /// consumed like [`PidSet::wait_any`], or [`InterruptOutcome::Interrupted`]
+    /// if `interrupt_fd` is readable, which takes precedence when both happen at
+    /// once; exits that are ready then stay queued for the next wait.
+    ///
+    /// The descriptor is neither owned nor consumed by the set: it is polled
+    /// together with the epoll instance for the duration of the call only, and
+    /// never read, so it keeps being readable until the caller drains it. It
+    /// must stay open during the call, and may also be watched with
+    /// [`PidSet::add_fd`]. If the set is empty, only the interrupt ends the wait.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::Poll` if `interrupt_fd` cannot be polled, e.g.
+    /// because it is not open, or `PidSetError` if the set cannot be initialized
+    /// or an error occurs during the wait.
+    pub fn wait_any_or_fd(&mut self, interrupt_fd: RawFd) -> Result<InterruptOutcome, PidSetError> {
+        let epoll_fd = self.get_or_init_epoll()?;
+        loop {
+            self.validate_before_wait()?;
+            // with no PID left, the epoll instance can only report user fds
+            let epoll_fd = if self.fd_pids.is_empty() {
+                -1
+            } else {
+                epoll_fd
+            };
+            let mut pollfds = [interrupt_fd, epoll_fd].map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            let sigmask = self
+                .sigmask
+                .as_ref()
+                .map_or(std::ptr::null(), |mask| mask as *const libc::sigset_t);
+            let polled = unsafe {
+                syserr(libc::ppoll(
+                    pollfds.as_mut_ptr(),
+                    pollfds.len() as libc::nfds_t,
+                    std::ptr::null(),
+                    sigmask,
+                ))
+            };
+            match polled {
+                // woken up by a signal
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                polled => polled.map_err(PidSetError::Poll)?,
+            };
+            let [interrupt, _] = pollfds;
+            if interrupt.revents & libc::POLLNVAL != 0 {
+                return Err(PidSetError::Poll(std::io::Error::from_raw_os_error(
+                    libc::EBADF,
+                )));
+            }
+            if interrupt.revents != 0 {
+                return Ok(InterruptOutcome::Interrupted);
+            }
+            // the epoll instance may also have been readable for a user fd only
+            if let Some(pid) = self.try_wait_any()? {
+                return Ok(InterruptOutcome::Exited(pid));
+            }
+        }
+    }
+
+    /// Keeps a pool of workers running: waits for each monitored PID to exit,
+    /// and monitors the replacement `respawn` returns for it instead, until the
+    /// set is empty.
+    ///
+    /// `respawn` is called with each exited PID, one at a time. Returning `None`
+    /// lets the pool shrink, so supervision ends once every worker exited
+    /// without a replacement. The replacement is registered with
+    /// [`PidSet::insert`], which is race-free as long as it is a child of this
+    /// process: it cannot be reaped, and its PID reused, before then. To stop
+    /// supervising before the pool is gone, e.g. on shutdown, use
+    /// [`PidSet::supervise_until`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during a wait, or if a
+    /// replacement cannot be monitored. The exited PID is consumed either way,
+    /// and the failed replacement is not in the set.
+    pub fn supervise<F>(&mut self, mut respawn: F) -> Result<(), PidSetError>
+    where
+        F: FnMut(PID) -> Option<PID>,
+    {
+        while let Some(exited) = self.wait_n(1)?.pop() {
+            if let Some(replacement) = respawn(exited) {
+                self.insert(replacement)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Like [`PidSet::supervise`], but also returns as soon as `interrupt_fd`
+    /// becomes readable, e.g. an eventfd written by a shutdown handler.
+    ///
+    /// Each wait is a [`PidSet::wait_any_or_fd`]: the interrupt takes precedence
+    /// over exits that are ready at the same time, which stay queued, and is not
+    /// consumed. The workers still running stay in the set, to be terminated or
+    /// waited for by the caller, and so does the interrupt: it has to be drained
+    /// before supervising again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::Poll` if `interrupt_fd` cannot be polled, or
+    /// `PidSetError` as [`PidSet::supervise`] does.
+    pub fn supervise_until<F>(
+        &mut self,
+        interrupt_fd: RawFd,
+        mut respawn: F,
+    ) -> Result<(), PidSetError>
+    where
+        F: FnMut(PID) -> Option<PID>,
+    {
+        while !self.fd_pids.is_empty() {
+            let InterruptOutcome::Exited(exited) = self.wait_any_or_fd(interrupt_fd)? else {
+                break;
+            };
+            if let Some(replacement) = respawn(exited) {
+                self.insert(replacement)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits until a monitored PID exits or a file descriptor added with
+    /// [`PidSet::add_fd`] changes readiness, and returns every event of the batch.
+    ///
+    /// Readiness changes already seen by other waits are returned first, without
+    /// blocking. With a `timeout`, an empty list is returned if it elapses first.
+    /// The list is empty as well if there is nothing left to wait for.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_events(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<PidSetEvent>, PidSetError> {
+        let epoll_fd = self.get_or_init_epoll()?;
+        let deadline = timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let mut events = Vec::new();
+        loop {
+            events.extend(
+                self.fd_events
+                    .drain(..)
+                    .map(|(token, events)| PidSetEvent::Fd { token, events }),
+            );
+            if !events.is_empty() || (self.fd_pids.is_empty() && self.user_fds.is_empty()) {
+                return Ok(events);
+            }
+            let timeout = timeout_ms(deadline);
+            let max_events = self.max_events() + self.user_fds.len();
+            self.wait_batch_with(epoll_fd, max_events, timeout, &mut |info| {
+                events.push(PidSetEvent::Exited(info))
+            })?;
+            events.extend(
+                self.fd_events
+                    .drain(..)
+                    .map(|(token, events)| PidSetEvent::Fd { token, events }),
+            );
+            if !events.is_empty() || timeout == 0 {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Returns `err` unless the [`PidSetBuilder::on_error`] hook lets the caller
+    /// carry on.
+    fn tolerate(&mut self, err: PidSetError) -> Result<(), PidSetError> {
+        match consult(&mut self.on_error, &err) {
+            ErrorAction::Continue => Ok(()),
+            ErrorAction::Abort => Err(err),
+        }
+    }
+
+    /// Stops monitoring `pid`, even if its pidfd cannot be deregistered: the
+    /// error is returned once it is closed and removed all the same.
+    fn forget(&mut self, epoll_fd: FD, Pid(pid): Pid, fd: PidFd) -> Result<(), PidSetError> {
+        // a oneshot pidfd is disarmed already, closing it is enough
+        let deregistered = if self.options.oneshot {
+            Ok(())
+        } else {
+            epoll_del(epoll_fd, fd)
+        };
+        fd.close();
+        self.fd_pids.remove(&pid);
+        self.tags.remove(&pid);
+        self.priorities.remove(&pid);
+        self.registered_at.remove(&pid);
+        deregistered
+    }
+
+    /// Records the readiness of a caller's file descriptor until it is returned
+    /// by [`PidSet::wait_events`].
+    fn queue_fd_event(&mut self, event: &libc::epoll_event) {
+        // removed since, but still in this batch
+        let Some(token) = fd_from_token(event.u64).and_then(|fd| self.user_fds.get(&fd).copied())
+        else {
+            return;
+        };
+        match self
+            .fd_events
+            .iter_mut()
+            .find(|(pending, _)| *pending == token)
+        {
+            Some((_, events)) => *events |= event.events,
+            None => self.fd_events.push((token, event.events)),
+        }
+    }
+
+    /// Closes the epoll file descriptor and cleans up the `PidSet`.
+    ///
+    /// If the set was never initialized (no wait or insert happened), there is
+    /// nothing to release and no syscall is made. Dropping a `PidSet` releases the
+    /// same resources, ignoring errors.
+    ///
+    /// Closing never blocks: the remaining processes are neither waited for nor
+    /// reaped, their pidfds are just closed, so a child stuck in an
+    /// uninterruptible state or ignoring signals cannot hang a shutdown. To
+    /// release the file descriptors but keep monitoring the PIDs later, use
+    /// [`PidSet::stop`] instead.
+    ///
+    /// Closing is strict: every descriptor is closed even if some fail, and the
+    /// failures are returned, e.g. for a pidfd closed behind the set's back by
+    /// code elsewhere in the process. For a best-effort teardown that never
+    /// fails, use [`PidSet::close_lenient`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::EpollClose` if the epoll file descriptor cannot be
+    /// closed, or else `PidSetError::InvalidPidFds` listing the pidfds that could
+    /// not be, every other descriptor being closed either way.
+    pub fn close(mut self) -> Result<(), PidSetError> {
+        self.release()
+    }
+
+    /// Closes every file descriptor like [`PidSet::close`], ignoring failures.
+    ///
+    /// This is the best-effort teardown of dropping the set, spelled out: each
+    /// descriptor is closed once, and a failure to close one is neither returned
+    /// nor retried, since the descriptor is invalid afterwards either way. The
+    /// crate has no logging of its own, so callers that want the failures, even
+    /// just to log them, use the strict [`PidSet::close`].
+    pub fn close_lenient(mut self) {
+        let _ = self.release();
+    }
+
+    /// Closes the epoll file descriptor and every pidfd but keeps the PIDs, so that
+    /// the set can be resumed later.
+    ///
+    /// Unlike [`PidSet::close`], which consumes the set for good, a stopped set
+    /// stays usable: it is back to the state of a [`PidSet::new`], and the next
+    /// [`PidSet::init`] or wait reopens and registers every PID. The options,
+    /// hooks, tags, priorities and exit records are kept. Descriptors added with
+    /// [`PidSet::add_fd`] are dropped along with the epoll instance, together with
+    /// their readiness not returned yet, and have to be added again.
+    ///
+    /// While stopped, nothing pins the processes: one that exits and is reaped
+    /// makes the resume fail with `PidSetError::PidFdOpenSyscall` (`ESRCH`), or,
+    /// if its PID is reused in the meantime, the resume monitors the new process.
+    /// Stopping a set that is not initialized only closes the pinned pidfds.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs while closing the epoll file
+    /// descriptor, in which case the pidfds are closed already.
+    pub fn stop(&mut self) -> Result<(), PidSetError> {
+        for (_, fd) in self.fd_pids.iter_mut() {
+            fd.close();
+            *fd = PidFd::UNOPENED;
+        }
+        self.user_fds.clear();
+        self.fd_events.clear();
+        if let Some(epoll_fd) = self.epoll_fd.take() {
+            unsafe { syserr(libc::close(epoll_fd)) }.map_err(PidSetError::EpollClose)?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> PidSet<S> {
+    /// Closes every pidfd owned by the set and the epoll file descriptor, if any,
+    /// even if closing some of them fails.
+    fn release(&mut self) -> Result<(), PidSetError> {
+        let mut invalid = Vec::new();
+        for (pid, fd) in self.fd_pids.drain() {
+            if !fd.is_open() {
+                continue;
+            }
+            if let Err(err) = unsafe { syserr(libc::close(fd.0)) } {
+                invalid.push((pid, err));
+            }
+        }
+        if let Some(epoll_fd) = self.epoll_fd.take() {
+            unsafe { syserr(libc::close(epoll_fd)) }.map_err(PidSetError::EpollClose)?;
+        }
+        if invalid.is_empty() {
+            Ok(())
+        } else {
+            Err(PidSetError::InvalidPidFds(invalid))
+        }
+    }
+}
+
+impl<S> Drop for PidSet<S> {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sleep_cmd(duration: &str) -> std::process::Command {
+        let mut cmd1 = std::process::Command::new("sleep");
+        cmd1.arg(duration);
+        cmd1
+    }
+
+    #[test]
+    fn wait_all() {
+        let mut pid_set = PidSet::new([
+            sleep_cmd("0.1").spawn().unwrap().id(),
+            sleep_cmd("0.2").spawn().unwrap().id(),
+            sleep_cmd("0.3").spawn().unwrap().id(),
+            sleep_cmd("0.4").spawn().unwrap().id(),
+            sleep_cmd("0.5").spawn().unwrap().id(),
+        ]);
+
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn wait_any() {
+        let start_time = Instant::now(); // Start the timer
+
+        let mut pid_set = PidSet::new([
+            sleep_cmd("0.2").spawn().unwrap().id(),
+            sleep_cmd("3").spawn().unwrap().id(),
+            sleep_cmd("3").spawn().unwrap().id(),
+            sleep_cmd("3").spawn().unwrap().id(),
+            sleep_cmd("3").spawn().unwrap().id(),
+        ]);
+
+        assert!(pid_set.wait_any().is_ok());
+        assert!(
+            start_time.elapsed() < Duration::from_secs(3),
+            "Expected wait_any() to return in less than 3 seconds, but it took {:?}",
+            start_time.elapsed()
+        );
+    }
+
+    #[test]
+    fn duplicate_token_in_batch() {
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+
+        let event = libc::epoll_event {
+            events: EPOLLIN as u32,
+            u64: pid as u64,
+        };
+        assert_eq!(
+            pid_set
+                .process_events(epoll_fd, &mut [event, event])
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(pid_set.is_empty());
+    }
+
+    /// Feeds pseudo-random batches of known, unknown and duplicate tokens to
+    /// `process_events` and checks the set's accounting after every batch.
+    #[test]
+    fn process_events_property() {
+        // xorshift64, seeded so that failures are reproducible
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        for round in 0..16 {
+            let pids: Vec<PID> = (0..8)
+                .map(|_| Command::new("true").spawn().unwrap().id())
+                .collect();
+            let mut pid_set = PidSet::new(pids.clone());
+            if round % 2 == 1 {
+                for pid in &pids {
+                    pid_set.set_priority(*pid, next(3) as i32).unwrap();
+                }
+            }
+            let epoll_fd = pid_set.init_epoll().unwrap();
+            let mut exited_count = 0;
+
+            while !pid_set.is_empty() {
+                let mut events: Vec<libc::epoll_event> = (0..next(6))
+                    .map(|_| {
+                        let token = match next(4) {
+                            // unknown to the set
+                            0 => 0,
+                            _ => pids[next(pids.len())],
+                        };
+                        libc::epoll_event {
+                            events: EPOLLIN as u32,
+                            u64: token as u64,
+                        }
+                    })
+                    .collect();
+                let monitored: HashSet<PID> = pid_set.fd_pids.keys().copied().collect();
+                let expected: HashSet<PID> = events
+                    .iter()
+                    .map(|event| event.u64 as PID)
+                    .filter(|pid| monitored.contains(pid))
+                    .collect();
+
+                let exits = pid_set.process_events(epoll_fd, &mut events).unwrap();
+                let returned: HashSet<PID> = exits.iter().map(|info| info.pid).collect();
+                assert_eq!(returned.len(), exits.len(), "no PID is returned twice");
+                assert_eq!(returned, expected);
+                assert_eq!(pid_set.len(), monitored.len() - exits.len());
+                exited_count += exits.len();
+                assert_eq!(pid_set.exited(), exited_count);
+            }
+            assert!(pid_set.close().is_ok());
+        }
+    }
+
+    #[test]
+    fn unknown_token_in_batch() {
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let unknown = libc::epoll_event {
+            events: EPOLLIN as u32,
+            u64: 0,
+        };
+        let event = libc::epoll_event {
+            events: EPOLLIN as u32,
+            u64: pid as u64,
+        };
+
+        let mut pid_set = PidSet::new([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        let exits = pid_set
+            .process_events(epoll_fd, &mut [unknown, event])
+            .unwrap();
+        assert_eq!(exits.len(), 1);
+        assert_eq!(exits[0].pid, pid);
+
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::builder().strict_tokens(true).build([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        assert!(matches!(
+            pid_set.process_events(epoll_fd, &mut [unknown]),
+            Err(PidSetError::PidNotFound(0))
+        ));
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn token_bounds() {
+        for pid in [0, 1, i32::MAX as PID, PID::MAX] {
+            assert_eq!(Pid::from_token(Pid(pid).token()), Some(Pid(pid)));
+            assert_eq!(fd_from_token(Pid(pid).token()), None);
+        }
+        for fd in [0, 1, FD::MAX] {
+            assert_eq!(fd_from_token(fd_token(fd)), Some(fd));
+            assert_eq!(Pid::from_token(fd_token(fd)), None);
+        }
+        assert_eq!(fd_from_token(fd_token(-1)), None);
+        // would truncate into PID 1, or fd 1, with a plain cast
+        let out_of_range = [
+            1 << 32 | 1,
+            FD_TOKEN_BIT - 1,
+            FD_TOKEN_BIT | 1 << 32 | 1,
+            u64::MAX,
+        ];
+        for token in out_of_range {
+            assert_eq!(Pid::from_token(token), None);
+            assert_eq!(fd_from_token(token), None);
+        }
+
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut events = out_of_range.map(|token| libc::epoll_event {
+            events: EPOLLIN as u32,
+            u64: token,
+        });
+        let mut pid_set = PidSet::new([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        // as if added, a truncated token would queue its events
+        pid_set.user_fds.insert(1, 7);
+        assert!(pid_set
+            .process_events(epoll_fd, &mut events)
+            .unwrap()
+            .is_empty());
+        assert_eq!(pid_set.len(), 1);
+        assert!(pid_set.fd_events.is_empty());
+
+        let mut pid_set = PidSet::builder().strict_tokens(true).build([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        let Err(PidSetError::InvalidToken(token)) = pid_set.process_events(epoll_fd, &mut events)
+        else {
+            panic!("out of range tokens are reported");
+        };
+        assert_eq!(token, 1 << 32 | 1);
+        assert!(PidSetError::InvalidToken(token)
+            .to_string()
+            .contains("`0x100000001`"));
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn on_error() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let running = sleep_cmd("0.1").spawn().unwrap().id();
+
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = errors.clone();
+        let mut pid_set = PidSet::builder()
+            .strict_tokens(true)
+            .on_error(move |err| {
+                seen.lock().unwrap().push(err.to_string());
+                ErrorAction::Continue
+            })
+            .build([reaped, running]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        assert_eq!(pid_set.active_pids().collect::<Vec<_>>(), [running]);
+        assert_eq!(pid_set.failed_pids()[0].0, reaped);
+
+        // the unknown token is skipped, the exit after it still processed
+        let unknown = libc::epoll_event {
+            events: EPOLLIN as u32,
+            u64: 0,
+        };
+        let event = libc::epoll_event {
+            events: EPOLLIN as u32,
+            u64: running as u64,
+        };
+        std::thread::sleep(Duration::from_millis(200));
+        let exits = pid_set
+            .process_events(epoll_fd, &mut [unknown, event])
+            .unwrap();
+        assert_eq!(exits.len(), 1);
+        assert!(pid_set.is_empty());
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].contains(&reaped.to_string()));
+        assert_eq!(errors[1], PidSetError::PidNotFound(0).to_string());
+
+        let mut pid_set = PidSet::builder()
+            .on_error(|_| ErrorAction::Abort)
+            .build([reaped]);
+        assert!(matches!(
+            pid_set.init(),
+            Err(PidSetError::PidFdOpenSyscall(pid, _)) if pid == reaped
+        ));
+    }
+
+    #[test]
+    fn on_error_drops_pids_without_hanging() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let running = sleep_cmd("0.1").spawn().unwrap().id();
+
+        let mut pid_set = PidSet::builder()
+            .on_error(|_| ErrorAction::Continue)
+            .build([reaped, running]);
+        assert!(pid_set.wait_all().is_ok());
+        assert!(pid_set.is_empty());
+
+        let mut pid_set = PidSet::builder()
+            .on_error(|_| ErrorAction::Continue)
+            .build([reaped]);
+        assert!(pid_set.wait_n(1).unwrap().is_empty());
+        let mut pid_set = PidSet::builder()
+            .on_error(|_| ErrorAction::Continue)
+            .build([reaped]);
+        assert!(matches!(
+            pid_set.wait_all_timeout(Duration::from_secs(5)).unwrap(),
+            WaitOutcome::Exited(pids) if pids.is_empty()
+        ));
+    }
+
+    #[test]
+    fn priority() {
+        let pids: Vec<PID> = (0..4)
+            .map(|_| sleep_cmd("0").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.clone());
+        pid_set.set_priority(pids[3], 10).unwrap();
+        pid_set.set_priority(pids[1], 5).unwrap();
+        pid_set.set_priority(pids[0], -1).unwrap();
+        assert_eq!(pid_set.priority(pids[2]), 0);
+        assert!(matches!(
+            pid_set.set_priority(0, 1),
+            Err(PidSetError::PidNotFound(0))
+        ));
+        let epoll_fd = pid_set.init_epoll().unwrap();
+
+        let mut events: Vec<libc::epoll_event> = pids
+            .iter()
+            .map(|pid| libc::epoll_event {
+                events: EPOLLIN as u32,
+                u64: *pid as u64,
+            })
+            .collect();
+        let order: Vec<PID> = pid_set
+            .process_events(epoll_fd, &mut events)
+            .unwrap()
+            .into_iter()
+            .map(|info| info.pid)
+            .collect();
+        assert_eq!(order, vec![pids[3], pids[1], pids[2], pids[0]]);
+        assert!(pid_set.priorities.is_empty());
+    }
+
+    #[test]
+    fn wait_all_with_progress() {
+        let mut pid_set = PidSet::new([
+            sleep_cmd("0.1").spawn().unwrap().id(),
+            sleep_cmd("0.2").spawn().unwrap().id(),
+            sleep_cmd("0.3").spawn().unwrap().id(),
+        ]);
+
+        let mut progress = Vec::new();
+        pid_set
+            .wait_all_with_progress(|exited, total| progress.push((exited, total)))
+            .unwrap();
+        assert_eq!(progress.last(), Some(&(3, 3)));
+        assert_eq!(pid_set.exited(), 3);
+        assert_eq!(pid_set.remaining(), 0);
+        assert!(progress.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn take_pidfd() {
+        let taken = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([taken, sleep_cmd("0.1").spawn().unwrap().id()]);
+        pid_set.init_epoll().unwrap();
+
+        let pidfd = pid_set.take_pidfd(taken).unwrap();
+        assert_eq!(pid_set.exited(), 0);
+        assert!(unsafe { libc::fcntl(pidfd.as_raw_fd(), libc::F_GETFD) } >= 0);
+        assert_eq!(pid_set.len(), 1);
+        assert!(matches!(
+            pid_set.take_pidfd(taken),
+            Err(PidSetError::PidNotFound(_))
+        ));
+
+        assert!(pid_set.wait_all().is_ok());
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn check_fd_limit() {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) },
+            0
+        );
+        if rlim.rlim_cur == libc::RLIM_INFINITY {
+            return;
+        }
+        // the PIDs are never opened, the pre-flight check must fail first
+        let pids = 1..=(rlim.rlim_cur as PID);
+
+        let mut pid_set = PidSet::builder().check_fd_limit(true).build(pids);
+        assert!(matches!(
+            pid_set.init_epoll(),
+            Err(PidSetError::FdLimitExceeded { .. })
+        ));
+        assert!(pid_set.epoll_fd.is_none());
+    }
+
+    #[test]
+    fn has_exited() {
+        let exited = sleep_cmd("0").spawn().unwrap().id();
+        let running = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([exited, running]);
+        std::thread::sleep(Duration::from_millis(100));
+
+        for _ in 0..2 {
+            assert!(pid_set.has_exited(exited).unwrap());
+            assert!(!pid_set.has_exited(running).unwrap());
+        }
+        assert_eq!(pid_set.len(), 2);
+        assert!(matches!(
+            pid_set.has_exited(0),
+            Err(PidSetError::PidNotFound(0))
+        ));
+
+        // the probed pidfds are registered by the first wait
+        assert!(pid_set.epoll_fd.is_none());
+        unsafe { libc::kill(running as i32, libc::SIGKILL) };
+        assert_eq!(pid_set.drain_wait_all().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn on_exit() {
+        let failed = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .spawn()
+            .unwrap()
+            .id();
+        let killed = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([failed, killed]);
+
+        let exits = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = exits.clone();
+        pid_set.on_exit(Box::new(move |pid, info| {
+            recorded.lock().unwrap().push((pid, info))
+        }));
+
+        pid_set.wait_any().unwrap();
+        unsafe { libc::kill(killed as i32, libc::SIGKILL) };
+        pid_set.wait_any().unwrap();
+
+        let exits = exits.lock().unwrap();
+        assert_eq!(
+            *exits,
+            vec![
+                (
+                    failed,
+                    Some(ExitInfo {
+                        pid: failed,
+                        code: Some(3),
+                        signal: None,
+                        is_child: true,
+                    })
+                ),
+                (
+                    killed,
+                    Some(ExitInfo {
+                        pid: killed,
+                        code: None,
+                        signal: Some(libc::SIGKILL),
+                        is_child: true,
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn signal_name() {
+        assert_eq!(super::signal_name(9), Some("SIGKILL"));
+        assert_eq!(super::signal_name(libc::SIGTERM), Some("SIGTERM"));
+        assert_eq!(super::signal_name(0), None);
+        assert_eq!(super::signal_name(-1), None);
+        assert_eq!(super::signal_name(1000), None);
+
+        let info = ExitInfo {
+            pid: 1,
+            code: None,
+            signal: Some(libc::SIGSEGV),
+            is_child: true,
+        };
+        assert_eq!(info.signal_name(), Some("SIGSEGV"));
+    }
+
+    #[test]
+    fn try_new() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        assert!(matches!(
+            PidSet::try_new([sleep_cmd("0.1").spawn().unwrap().id(), reaped]),
+            Err(PidSetError::PidFdOpenSyscall(pid, _)) if pid == reaped
+        ));
+
+        let mut pid_set = PidSet::try_new([sleep_cmd("0.1").spawn().unwrap().id()]).unwrap();
+        assert!(pid_set.epoll_fd.is_some());
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn set_max_events() {
+        let mut pid_set = PidSet::new([
+            sleep_cmd("0.1").spawn().unwrap().id(),
+            sleep_cmd("0.1").spawn().unwrap().id(),
+            sleep_cmd("0.1").spawn().unwrap().id(),
+        ]);
+        assert_eq!(pid_set.max_events(), 3);
+        pid_set.set_max_events(0);
+        assert_eq!(pid_set.max_events(), 1);
+        pid_set.set_max_events(10);
+        assert_eq!(pid_set.max_events(), 3);
+
+        pid_set.set_max_events(1);
+        let mut batches = 0;
+        pid_set.wait_all_with_progress(|_, _| batches += 1).unwrap();
+        assert_eq!(batches, 3);
+    }
+
+    #[test]
+    fn wait_all_or_kill() {
+        let quick = sleep_cmd("0").spawn().unwrap().id();
+        let stuck = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([quick, stuck]);
+
+        let start_time = Instant::now();
+        let mut exits = pid_set
+            .wait_all_or_kill(Duration::from_millis(200), libc::SIGTERM)
+            .unwrap();
+        assert!(start_time.elapsed() >= Duration::from_millis(200));
+        assert!(start_time.elapsed() < Duration::from_secs(5));
+
+        exits.sort_by_key(|info| info.pid != quick);
+        assert_eq!(exits[0].code, Some(0));
+        assert_eq!(exits[1].pid, stuck);
+        assert_eq!(exits[1].signal, Some(libc::SIGTERM));
+        assert!(pid_set.is_empty());
+
+        // a timeout that never elapses waits for every PID without signaling
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        let exits = pid_set
+            .wait_all_or_kill(Duration::MAX, libc::SIGKILL)
+            .unwrap();
+        assert_eq!(exits[0].code, Some(0));
+    }
+
+    #[test]
+    fn add_pidfd() {
+        let labeled = sleep_cmd("0.1").spawn().unwrap().id();
+        let unlabeled = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([]);
+
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd_open(Pid(labeled)).unwrap().0) };
+        assert_eq!(pid_set.add_pidfd(pidfd, Some(labeled)).unwrap(), labeled);
+        pid_set.init_epoll().unwrap();
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd_open(Pid(unlabeled)).unwrap().0) };
+        assert_eq!(pid_set.add_pidfd(pidfd, None).unwrap(), unlabeled);
+
+        assert_eq!(pid_set.len(), 2);
+        assert!(pid_set.wait_all().is_ok());
+
+        // a reaped process has no PID to label it with
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd_open(Pid(child.id())).unwrap().0) };
+        child.wait().unwrap();
+        assert!(matches!(
+            pid_set.add_pidfd(pidfd, None),
+            Err(PidSetError::PidFdInfo(_))
+        ));
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn epoll_fd_select() {
+        fn readable(fd: RawFd, timeout: Duration) -> bool {
+            let mut read_fds: libc::fd_set = unsafe { std::mem::zeroed() };
+            unsafe { libc::FD_SET(fd, &mut read_fds) };
+            let mut timeout = libc::timeval {
+                tv_sec: timeout.as_secs() as libc::time_t,
+                tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+            };
+            let ready = unsafe {
+                libc::select(
+                    fd + 1,
+                    &mut read_fds,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    &mut timeout,
+                )
+            };
+            ready > 0 && unsafe { libc::FD_ISSET(fd, &read_fds) }
+        }
+
+        let pid = sleep_cmd("0.2").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        let epoll_fd = pid_set.epoll_fd().unwrap();
+
+        assert!(!readable(epoll_fd, Duration::ZERO));
+        assert!(readable(epoll_fd, Duration::from_secs(5)));
+        assert_eq!(pid_set.try_wait_any().unwrap(), Some(pid));
+        assert!(!readable(epoll_fd, Duration::ZERO));
+    }
+
+    #[test]
+    fn wait_all_partitioned() {
+        let exit_cmd = |code: i32| {
+            std::process::Command::new("sh")
+                .args(["-c", &format!("exit {code}")])
+                .spawn()
+                .unwrap()
+                .id()
+        };
+        let succeeded = exit_cmd(0);
+        let failed = exit_cmd(1);
+        let mut pid_set = PidSet::new([succeeded, failed]);
+
+        let (ok, err) = pid_set.wait_all_partitioned().unwrap();
+        assert_eq!(
+            ok.iter().map(|info| info.pid).collect::<Vec<_>>(),
+            [succeeded]
+        );
+        assert_eq!(
+            err.iter().map(|info| info.pid).collect::<Vec<_>>(),
+            [failed]
+        );
+        assert_eq!(err[0].code, Some(1));
+    }
+
+    #[test]
+    fn drain_wait_all() {
+        let exit_cmd = |code: i32| {
+            std::process::Command::new("sh")
+                .args(["-c", &format!("exit {code}")])
+                .spawn()
+                .unwrap()
+                .id()
+        };
+        let pids = [exit_cmd(0), exit_cmd(2)];
+        let mut pid_set = PidSet::new(pids);
+
+        let mut exits = pid_set.drain_wait_all().unwrap();
+        exits.sort_by_key(|info| info.code);
+        assert_eq!(exits.iter().map(|info| info.pid).collect::<Vec<_>>(), pids);
+        assert_eq!(exits[1].code, Some(2));
+        assert!(pid_set.is_empty());
+
+        let epoll_fd = pid_set.epoll_fd().unwrap();
+        let pid = exit_cmd(0);
+        pid_set.insert(pid).unwrap();
+        assert_eq!(pid_set.epoll_fd().unwrap(), epoll_fd);
+        assert_eq!(pid_set.drain_wait_all().unwrap()[0].pid, pid);
+        assert!(pid_set.drain_wait_all().unwrap().is_empty());
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn retag() {
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        assert_eq!(pid_set.tag(pid), None);
+
+        pid_set.retag(pid, 1).unwrap();
+        pid_set.init_epoll().unwrap();
+        pid_set.retag(pid, 2).unwrap();
+        assert_eq!(pid_set.tag(pid), Some(2));
+        assert!(matches!(
+            pid_set.retag(0, 3),
+            Err(PidSetError::PidNotFound(0))
+        ));
+
+        pid_set.wait_all().unwrap();
+        assert_eq!(pid_set.tag(pid), None);
+    }
+
+    #[test]
+    fn with_tags() {
+        let first = sleep_cmd("0.1").spawn().unwrap().id();
+        let second = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::with_tags([(first, 1), (second, 2), (first, 3)]);
+        assert_eq!(pid_set.len(), 2);
+        assert_eq!(pid_set.tag(first), Some(3));
+        assert_eq!(pid_set.tag(second), Some(2));
+        pid_set.wait_all().unwrap();
+
+        let mut pid_set = PidSet::with_tags([(0, 1)]);
+        assert!(matches!(pid_set.init(), Err(PidSetError::InvalidPid(0))));
+    }
+
+    #[test]
+    fn retain_exited() {
+        let pid = sleep_cmd("0").spawn().unwrap().id();
+        let failed = Command::new("false").spawn().unwrap().id();
+        let mut pid_set = PidSet::builder().retain_exited(true).build([pid, failed]);
+        assert!(pid_set.exit_info(pid).is_none());
+        assert_eq!(pid_set.exited_iter().count(), 0);
+
+        pid_set.wait_all().unwrap();
+        assert!(pid_set.is_empty());
+        assert_eq!(pid_set.exit_info(pid).unwrap().code, Some(0));
+        let mut codes: Vec<(PID, Option<i32>)> = pid_set
+            .exited_iter()
+            .map(|(pid, info)| (pid, info.code))
+            .collect();
+        codes.sort_unstable();
+        let mut expected = vec![(pid, Some(0)), (failed, Some(1))];
+        expected.sort_unstable();
+        assert_eq!(codes, expected);
+
+        let mut taken = pid_set.take_exited();
+        taken.sort_unstable_by_key(|(pid, _)| *pid);
+        assert_eq!(
+            taken
+                .iter()
+                .map(|(pid, info)| (*pid, info.code))
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!(pid_set.exit_info(pid).is_none());
+        assert!(pid_set.take_exited().is_empty());
+        assert_eq!(pid_set.exited(), 2);
+
+        let pid = sleep_cmd("0").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        pid_set.wait_all().unwrap();
+        assert!(pid_set.exit_info(pid).is_none());
+        assert_eq!(pid_set.exited_iter().count(), 0);
+    }
+
+    #[test]
+    fn ready_pids_are_all_served() {
+        let pids: Vec<PID> = (0..5)
+            .map(|_| sleep_cmd("0").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.clone());
+        pid_set.set_max_events(1);
+        std::thread::sleep(Duration::from_millis(200));
+
+        let mut served = Vec::new();
+        while let Some(pid) = pid_set.try_wait_any().unwrap() {
+            served.push(pid);
+        }
+        served.sort();
+        let mut expected = pids;
+        expected.sort();
+        assert_eq!(served, expected);
+    }
+
+    #[test]
+    fn spawn_and_monitor() {
+        let mut pid_set = PidSet::new([]);
+        let pid = pid_set.spawn_and_monitor(&mut sleep_cmd("0.1")).unwrap();
+        assert_eq!(pid_set.len(), 1);
+        assert!(matches!(
+            pid_set.spawn_and_monitor(&mut Command::new("/nonexistent")),
+            Err(PidSetError::Spawn(_))
+        ));
+        assert_eq!(pid_set.len(), 1);
+
+        let (ok, _) = pid_set.wait_all_partitioned().unwrap();
+        assert_eq!(ok[0].pid, pid);
+    }
+
+    #[test]
+    fn wait_timeout() {
+        let quick = sleep_cmd("0").spawn().unwrap().id();
+        let stuck = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([quick, stuck]);
+
+        assert_eq!(
+            pid_set
+                .wait_all_timeout(Duration::from_millis(200))
+                .unwrap(),
+            WaitOutcome::TimedOut(vec![quick])
+        );
+        assert_eq!(
+            pid_set.wait_any_timeout(Duration::ZERO).unwrap(),
+            WaitOutcome::TimedOut(vec![])
+        );
+
+        unsafe { libc::kill(stuck as i32, libc::SIGKILL) };
+        assert_eq!(
+            pid_set.wait_any_timeout(Duration::from_secs(5)).unwrap(),
+            WaitOutcome::Exited(vec![stuck])
+        );
+        assert_eq!(
+            pid_set.wait_all_timeout(Duration::ZERO).unwrap(),
+            WaitOutcome::Exited(vec![])
+        );
+
+        let pid = sleep_cmd("0").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        assert_eq!(
+            pid_set.wait_any_timeout(Duration::MAX).unwrap(),
+            WaitOutcome::Exited(vec![pid])
+        );
+    }
+
+    #[test]
+    fn init_lenient() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let alive = sleep_cmd("0.1").spawn().unwrap().id();
+
+        let mut pid_set = PidSet::new([alive, reaped]);
+        let report = pid_set.init_lenient().unwrap();
+        assert_eq!(report.registered, [alive]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, reaped);
+        assert_eq!(report.failed[0].1.raw_os_error(), Some(libc::ESRCH));
+        assert_eq!(pid_set.len(), 1);
+
+        assert_eq!(pid_set.init_lenient().unwrap().registered, [alive]);
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn oneshot() {
+        let mut pid_set = PidSet::builder().oneshot(true).build([
+            sleep_cmd("0.1").spawn().unwrap().id(),
+            sleep_cmd("0.2").spawn().unwrap().id(),
         ]);
+        assert!(pid_set.wait_any().is_ok());
+        assert_eq!(pid_set.len(), 1);
+        assert!(pid_set.wait_all().is_ok());
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn pin() {
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        pid_set.pin().unwrap();
+        assert!(pid_set.epoll_fd.is_none());
+        let pinned = pid_set.fd_pids[&pid];
+        assert!(pinned.is_open());
+
+        pid_set.pin().unwrap();
+        pid_set.init_epoll().unwrap();
+        assert_eq!(pid_set.fd_pids[&pid], pinned);
+        assert!(pid_set.wait_all().is_ok());
+
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let mut pid_set = PidSet::new([sleep_cmd("0.1").spawn().unwrap().id(), reaped]);
+        assert!(pid_set.pin().is_err());
+        assert!(pid_set.fd_pids.values().all(|fd| !fd.is_open()));
+    }
+
+    #[test]
+    fn into_exit_iter() {
+        let first = sleep_cmd("0.1").spawn().unwrap().id();
+        let second = sleep_cmd("0.3").spawn().unwrap().id();
+        let order: Vec<PID> = PidSet::new([second, first])
+            .into_exit_iter()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(order, [first, second]);
+
+        let mut exits = PidSet::new([sleep_cmd("0").spawn().unwrap().id()]).into_exit_iter();
+        assert!(matches!(exits.next(), Some(Ok(_))));
+        assert!(exits.next().is_none());
+    }
+
+    #[test]
+    fn pidfd_open_retry() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+
+        let mut pid_set = PidSet::builder()
+            .pidfd_open_retries(2, Duration::from_millis(20))
+            .build([reaped]);
+        let start_time = Instant::now();
+        assert!(matches!(
+            pid_set.init_epoll(),
+            Err(PidSetError::PidFdOpenSyscall(pid, _)) if pid == reaped
+        ));
+        // 20ms, then 40ms of backoff before giving up
+        assert!(start_time.elapsed() >= Duration::from_millis(60));
+
+        let mut pid_set = PidSet::builder()
+            .pidfd_open_retries(2, Duration::from_secs(5))
+            .build([sleep_cmd("0.1").spawn().unwrap().id()]);
+        let start_time = Instant::now();
+        assert!(pid_set.wait_all().is_ok());
+        assert!(start_time.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn status_report() {
+        let pids: Vec<PID> = (0..STATUS_REPORT_PIDS + 2)
+            .map(|_| sleep_cmd("0.1").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids);
+
+        let report = pid_set.status_report();
+        assert!(report.contains(&format!("monitored: {}", STATUS_REPORT_PIDS + 2)));
+        assert!(report.contains("epoll: not initialized"));
+        assert!(report.contains("fds: 0 open"));
+        assert!(report.contains("... and 2 more"));
 
+        pid_set.wait_all().unwrap();
+        let report = pid_set.status_report();
+        assert!(report.contains("monitored: 0"));
+        assert!(report.contains(&format!("exited: {}", STATUS_REPORT_PIDS + 2)));
+        assert!(report.contains("epoll: initialized"));
+        assert!(report.contains("fds: 1 open"));
+        assert!(!report.contains("more"));
+    }
+
+    #[test]
+    fn dump_interest() {
+        let pids: Vec<PID> = (0..3)
+            .map(|_| sleep_cmd("0.1").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.clone());
+        pid_set.pin().unwrap();
+        assert!(pid_set.dump_interest().is_empty());
+
+        let epoll_fd = pid_set.epoll_fd().unwrap();
+        let interest = pid_set.dump_interest();
+        let mut sorted = pids.clone();
+        sorted.sort_unstable();
+        assert_eq!(
+            interest.iter().map(|(pid, _)| *pid).collect::<Vec<_>>(),
+            sorted
+        );
+
+        // the kernel watches exactly the dumped pidfds
+        let fdinfo = std::fs::read_to_string(format!("/proc/self/fdinfo/{epoll_fd}")).unwrap();
+        let mut watched: Vec<RawFd> = fdinfo
+            .lines()
+            .filter_map(|line| line.strip_prefix("tfd:"))
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect();
+        watched.sort_unstable();
+        let mut dumped: Vec<RawFd> = interest.iter().map(|(_, fd)| *fd).collect();
+        dumped.sort_unstable();
+        assert_eq!(watched, dumped);
+
+        pid_set.wait_all().unwrap();
+        assert!(pid_set.dump_interest().is_empty());
+    }
+
+    #[test]
+    fn stop() {
+        let pids: Vec<PID> = (0..2)
+            .map(|_| sleep_cmd("0.3").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::try_new(pids.clone()).unwrap();
+        pid_set.stop().unwrap();
+        assert!(pid_set.epoll_fd.is_none());
+        assert!(pid_set.fd_pids.values().all(|fd| !fd.is_open()));
+        assert_eq!(pid_set.len(), 2);
+        assert!(pid_set.dump_interest().is_empty());
+
+        // resumed by the next wait
+        let mut exited = pid_set.wait_n(2).unwrap();
+        exited.sort_unstable();
+        let mut pids = pids;
+        pids.sort_unstable();
+        assert_eq!(exited, pids);
+        pid_set.stop().unwrap();
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn exclusive() {
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::builder().exclusive(true).build([pid]);
         assert!(pid_set.wait_all().is_ok());
+
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::builder().exclusive(true).oneshot(true).build([pid]);
+        assert!(matches!(
+            pid_set.init_epoll(),
+            Err(PidSetError::EpollCtl(_))
+        ));
     }
 
     #[test]
-    fn wait_any() {
-        let start_time = Instant::now(); // Start the timer
+    fn error_messages() {
+        let io = || std::io::Error::from_raw_os_error(libc::EBADF);
+        let inner = io().to_string();
+        let errors = [
+            PidSetError::EpollCreate(io()),
+            PidSetError::PidFdOpenSyscall(1, io()),
+            PidSetError::EpollCtl(io()),
+            PidSetError::EpollWait(io()),
+            PidSetError::EpollClose(io()),
+            PidSetError::EventFd(io()),
+            PidSetError::SigMask(io()),
+            PidSetError::SignalFd(io()),
+            PidSetError::WaitPid(1, io()),
+            PidSetError::PidFdSendSignal(1, io()),
+            PidSetError::PidFdInfo(io()),
+            PidSetError::Spawn(io()),
+            PidSetError::Poll(io()),
+            PidSetError::GetRlimit(io()),
+        ];
+        for err in errors {
+            let message = err.to_string();
+            assert_eq!(message.matches('`').count() % 2, 0, "{message}");
+            assert!(message.contains(&format!("`{inner}`")), "{message}");
+            assert!(message.contains(&format!("os error {}", libc::EBADF)));
+        }
 
-        let mut pid_set = PidSet::new([
-            sleep_cmd("0.2").spawn().unwrap().id(),
-            sleep_cmd("3").spawn().unwrap().id(),
-            sleep_cmd("3").spawn().unwrap().id(),
-            sleep_cmd("3").spawn().unwrap().id(),
-            sleep_cmd("3").spawn().unwrap().id(),
-        ]);
+        let others = [
+            PidSetError::PidNotFound(1),
+            PidSetError::FdError(1),
+            PidSetError::ThreadId { tid: 1, tgid: 2 },
+            PidSetError::PidAlreadyMonitored(1),
+            PidSetError::InvalidPid(1),
+            PidSetError::InvalidPidFds(vec![(1, io())]),
+            PidSetError::FdLimitExceeded {
+                required: 2,
+                limit: 1,
+            },
+        ];
+        for err in others {
+            let message = err.to_string();
+            assert_eq!(message.matches('`').count() % 2, 0, "{message}");
+            assert!(message.contains("`1`"), "{message}");
+        }
+
+        // syscall failures carry the errno, not the -1 return value
+        let err = syserr(unsafe { libc::close(-1) }).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+    }
+
+    #[test]
+    fn wait_n() {
+        let first = sleep_cmd("0.1").spawn().unwrap().id();
+        let second = sleep_cmd("0.5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([first, second]);
+
+        let result = pid_set.wait(1).unwrap();
+        assert_eq!(result.exited, vec![first]);
+        assert_eq!(result.remaining, 1);
+
+        let result = pid_set.wait(5).unwrap();
+        assert_eq!(result.exited, vec![second]);
+        assert_eq!(result.remaining, 0);
+        assert!(pid_set.wait(1).unwrap().exited.is_empty());
+    }
+
+    #[test]
+    fn replace_pid() {
+        let old = sleep_cmd("5").spawn().unwrap().id();
+        let other = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([old, other]);
+        pid_set.retag(old, 7).unwrap();
+        pid_set.init_epoll().unwrap();
+
+        let new = sleep_cmd("0.1").spawn().unwrap().id();
+        assert!(matches!(
+            pid_set.replace_pid(new, old),
+            Err(PidSetError::PidNotFound(pid)) if pid == new
+        ));
+        assert!(matches!(
+            pid_set.replace_pid(old, other),
+            Err(PidSetError::PidAlreadyMonitored(pid)) if pid == other
+        ));
+        pid_set.replace_pid(old, new).unwrap();
+        assert_eq!(pid_set.len(), 2);
+        assert_eq!(pid_set.tag(new), Some(7));
+        assert_eq!(pid_set.tag(old), None);
+
+        let start_time = Instant::now();
+        assert!(pid_set.wait_all().is_ok());
+        assert!(start_time.elapsed() < Duration::from_secs(5));
+        unsafe { libc::kill(old as libc::pid_t, libc::SIGKILL) };
+
+        // failing to deregister `old` rolls the registration of `new` back
+        let old = sleep_cmd("5").spawn().unwrap().id();
+        let new = sleep_cmd("0").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([old]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        let old_fd = pid_set.fd_pids[&old];
+        epoll_del(epoll_fd, old_fd).unwrap();
+        assert!(matches!(
+            pid_set.replace_pid(old, new),
+            Err(PidSetError::EpollCtl(err)) if err.raw_os_error() == Some(libc::ENOENT)
+        ));
+        assert_eq!(pid_set.active_pids().collect::<Vec<_>>(), [old]);
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let flags = libc::WEXITED | libc::WNOWAIT;
+        assert_eq!(
+            unsafe { libc::waitid(libc::P_PID, new as libc::id_t, &mut info, flags) },
+            0
+        );
+        let mut event = libc::epoll_event { events: 0, u64: 0 };
+        assert_eq!(unsafe { libc::epoll_wait(epoll_fd, &mut event, 1, 0) }, 0);
+
+        epoll_add_pidfd(epoll_fd, old_fd, Pid(old), pid_set.epoll_events()).unwrap();
+        unsafe { libc::kill(old as libc::pid_t, libc::SIGKILL) };
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn pid_boundaries() {
+        for invalid in [0, i32::MAX as PID + 1, u32::MAX] {
+            let mut pid_set = PidSet::new([invalid]);
+            assert!(matches!(
+                pid_set.init_epoll(),
+                Err(PidSetError::InvalidPid(pid)) if pid == invalid
+            ));
+        }
+        // in range, but above any pid_max the kernel accepts (2^22)
+        for missing in [(1 << 22) + 1, i32::MAX as PID] {
+            let mut pid_set = PidSet::new([missing]);
+            assert!(matches!(
+                pid_set.init_epoll(),
+                Err(PidSetError::PidFdOpenSyscall(pid, _)) if pid == missing
+            ));
+        }
+
+        let mut pid_set = PidSet::new([u32::MAX]);
+        let report = pid_set.init_lenient().unwrap();
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].1.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn init() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let mut pid_set = PidSet::new([reaped]);
+        assert!(matches!(
+            pid_set.init(),
+            Err(PidSetError::PidFdOpenSyscall(pid, _)) if pid == reaped
+        ));
+        assert!(pid_set.epoll_fd.is_none());
+
+        let mut pid_set = PidSet::new([sleep_cmd("0.1").spawn().unwrap().id()]);
+        pid_set.init().unwrap();
+        let epoll_fd = pid_set.epoll_fd;
+        assert!(epoll_fd.is_some());
+        pid_set.init().unwrap();
+        assert_eq!(pid_set.epoll_fd, epoll_fd);
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn into_channel() {
+        let pids: HashSet<PID> = (0..3)
+            .map(|_| sleep_cmd("0.1").spawn().unwrap().id())
+            .collect();
+        let receiver = PidSet::new(pids.clone()).into_channel();
+        let exited: HashSet<PID> = receiver.iter().map(Result::unwrap).collect();
+        assert_eq!(exited, pids);
+
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let receiver = PidSet::new([reaped]).into_channel();
+        assert!(matches!(
+            receiver.recv(),
+            Ok(Err(PidSetError::PidFdOpenSyscall(pid, _))) if pid == reaped
+        ));
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn send_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+
+        assert_send::<PidSet>();
+        assert_send::<ExitIter>();
+        assert_send::<SharedPidSet>();
+        assert_sync::<SharedPidSet>();
+        assert_send::<SignalFdSet>();
+        assert_send::<PidSetError>();
+        assert_sync::<PidSetError>();
+        // can be spawned on multi-threaded executors
+        assert_send::<future::JoinAll<RandomState>>();
+        fn assert_send_value<T: Send>(_: &T) {}
+        assert_send_value(&PidSet::new([]).join_all());
+        assert_send::<ExitFuture>();
+        let mut pid_set = PidSet::new([]);
+        assert_send_value(&pid_set.wait_empty());
+        assert_send::<ExitStream>();
+        assert_send::<BatchedExitStream>();
+    }
+
+    #[test]
+    fn with_hasher() {
+        /// Identity hashing, fine for trusted integer keys.
+        #[derive(Default)]
+        struct PidHasher(u64);
+
+        impl std::hash::Hasher for PidHasher {
+            fn finish(&self) -> u64 {
+                self.0
+            }
+
+            fn write(&mut self, bytes: &[u8]) {
+                for byte in bytes {
+                    self.0 = self.0 << 8 | *byte as u64;
+                }
+            }
+
+            fn write_u32(&mut self, n: u32) {
+                self.0 = n as u64;
+            }
+        }
+
+        let pids: HashMap<PID, ()> = (0..3)
+            .map(|_| (sleep_cmd("0.1").spawn().unwrap().id(), ()))
+            .collect();
+        let mut pid_set = PidSet::with_hasher(
+            pids.into_keys(),
+            std::hash::BuildHasherDefault::<PidHasher>::default(),
+        );
+        assert_eq!(pid_set.len(), 3);
+        assert!(pid_set.wait_all().is_ok());
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn close_does_not_block() {
+        let stuck = std::process::Command::new("sh")
+            .args(["-c", "trap '' TERM INT; sleep 5"])
+            .spawn()
+            .unwrap()
+            .id();
+        let mut pid_set = PidSet::new([stuck]);
+        pid_set.init().unwrap();
+        assert!(!pid_set.has_exited(stuck).unwrap());
+
+        let start_time = Instant::now();
+        assert!(pid_set.close().is_ok());
+        assert!(start_time.elapsed() < Duration::from_secs(1));
+        unsafe { libc::kill(stuck as i32, libc::SIGKILL) };
+    }
+
+    #[test]
+    fn wait_n_exact() {
+        let pids: Vec<PID> = (0..5)
+            .map(|_| sleep_cmd("0").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.clone());
+        std::thread::sleep(Duration::from_millis(100));
+
+        // all five are ready, only three are consumed
+        let first = pid_set.wait_n(3).unwrap();
+        assert_eq!(first.len(), 3);
+        assert_eq!(pid_set.len(), 2);
+
+        let rest = pid_set.wait_n(10).unwrap();
+        assert_eq!(rest.len(), 2);
+        let mut all: Vec<PID> = first.into_iter().chain(rest).collect();
+        all.sort_unstable();
+        let mut pids = pids;
+        pids.sort_unstable();
+        assert_eq!(all, pids);
+        assert!(pid_set.wait_n(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn exit_info_of_non_child() {
+        use std::io::BufRead;
+
+        // the grandchild is reaped by the shell, not by this process
+        let mut shell = std::process::Command::new("sh")
+            .args(["-c", "sh -c 'sleep 0.1; exit 4' & echo $!; wait"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut line = String::new();
+        std::io::BufReader::new(shell.stdout.take().unwrap())
+            .read_line(&mut line)
+            .unwrap();
+        let grandchild: PID = line.trim().parse().unwrap();
+        let mut pid_set = PidSet::new([grandchild]);
+        pid_set.init().unwrap();
+        shell.wait().unwrap();
+
+        let (_, failed) = pid_set.wait_all_partitioned().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert!(!failed[0].is_child);
+        if PIDFD_INFO_SUPPORTED.load(Ordering::Relaxed) && failed[0].code.is_some() {
+            assert_eq!(failed[0].code, Some(4));
+        } else {
+            // kernel without PIDFD_INFO_EXIT
+            assert_eq!(failed[0], ExitInfo::unknown(grandchild));
+        }
+        assert_eq!(std::mem::size_of::<PidfdInfo>(), 64);
+    }
+
+    #[test]
+    fn failed_pids() {
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let reaped = child.id();
+        child.wait().unwrap();
+        let running = sleep_cmd("0.1").spawn().unwrap().id();
+
+        let mut pid_set = PidSet::new([reaped, running]);
+        assert!(pid_set.failed_pids().is_empty());
+        pid_set.init_lenient().unwrap();
+        assert_eq!(pid_set.active_pids().collect::<Vec<_>>(), vec![running]);
+        let failed = pid_set.failed_pids();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, reaped);
+        assert_eq!(failed[0].1.raw_os_error(), Some(libc::ESRCH));
+
+        // still recorded after the waits
+        pid_set.wait_all().unwrap();
+        assert_eq!(pid_set.failed_pids().len(), 1);
+
+        // the reaped PID cannot be reopened, any pidfd will do to add it back
+        let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd_open(Pid(running)).unwrap().0) };
+        pid_set.add_pidfd(pidfd, Some(reaped)).unwrap();
+        assert!(pid_set.failed_pids().is_empty());
+    }
+
+    #[test]
+    fn signal_mask() {
+        let mut mask: libc::sigset_t = unsafe { std::mem::zeroed() };
+        unsafe { libc::sigfillset(&mut mask) };
+        let mut pid_set = PidSet::new([sleep_cmd("0.1").spawn().unwrap().id()]);
+        pid_set.set_signal_mask(Some(mask));
+        assert!(pid_set.wait_all().is_ok());
+
+        // the thread's own mask is left untouched
+        let mut current: libc::sigset_t = unsafe { std::mem::zeroed() };
+        unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, std::ptr::null(), &mut current) };
+        assert_eq!(unsafe { libc::sigismember(&current, libc::SIGUSR1) }, 0);
+
+        pid_set.set_signal_mask(None);
+        assert!(pid_set.sigmask.is_none());
+    }
+
+    #[test]
+    fn wait_interrupted() {
+        extern "C" fn ignore(_: libc::c_int) {}
+        unsafe { libc::signal(libc::SIGUSR2, ignore as *const () as libc::sighandler_t) };
+        let waiter = unsafe { libc::pthread_self() };
+        let interrupt = move || {
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                unsafe { libc::pthread_kill(waiter, libc::SIGUSR2) };
+            })
+        };
+
+        // the signal wakes the blocking wait up, which carries on
+        let pid = sleep_cmd("0.2").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        let interrupter = interrupt();
+        assert_eq!(pid_set.wait_n(1).unwrap(), [pid]);
+        interrupter.join().unwrap();
+
+        // and a timed wait, for what is left of the timeout
+        let stuck = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([stuck]);
+        let interrupter = interrupt();
+        let start = Instant::now();
+        assert_eq!(
+            pid_set
+                .wait_any_timeout(Duration::from_millis(200))
+                .unwrap(),
+            WaitOutcome::TimedOut(vec![])
+        );
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_secs(2));
+        interrupter.join().unwrap();
+        unsafe { libc::kill(stuck as i32, libc::SIGKILL) };
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn reap() {
+        let child = std::process::Command::new("sh")
+            .args(["-c", "exit 5"])
+            .spawn()
+            .unwrap()
+            .id();
+        let mut pid_set = PidSet::new([child]);
+        assert!(matches!(
+            pid_set.reap(child),
+            Err(PidSetError::PidNotFound(pid)) if pid == child
+        ));
+        pid_set.wait_all().unwrap();
+
+        // a zombie until reaped
+        let stat = std::fs::read_to_string(format!("/proc/{child}/stat")).unwrap();
+        assert!(stat.contains(") Z "));
+        let info = pid_set.reap(child).unwrap();
+        assert_eq!(info.code, Some(5));
+        assert!(info.is_child);
+        assert!(!std::path::Path::new(&format!("/proc/{child}")).exists());
+        assert!(matches!(
+            pid_set.reap(child),
+            Err(PidSetError::PidNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn reap_state() {
+        let child = sleep_cmd("0.1").spawn().unwrap().id();
+        let running = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::builder()
+            .retain_exited(true)
+            .build([child, running]);
+        assert_eq!(pid_set.reap_state(child), Some(ReapState::Running));
+        assert_eq!(pid_set.reap_state(1), None);
+
+        assert_eq!(pid_set.wait_n(1).unwrap(), [child]);
+        assert_eq!(pid_set.reap_state(child), Some(ReapState::Exited));
+        assert_eq!(pid_set.reap_state(running), Some(ReapState::Running));
+        pid_set.reap(child).unwrap();
+        assert_eq!(pid_set.reap_state(child), Some(ReapState::Reaped));
+        pid_set.take_exited();
+        assert_eq!(pid_set.reap_state(child), None);
+
+        // without records, a reaped PID is forgotten
+        let mut pid_set = PidSet::new([running]);
+        unsafe { libc::kill(running as i32, libc::SIGKILL) };
+        pid_set.wait_all().unwrap();
+        assert_eq!(pid_set.reap_state(running), Some(ReapState::Exited));
+        pid_set.reap(running).unwrap();
+        assert_eq!(pid_set.reap_state(running), None);
+    }
+
+    #[test]
+    fn wait_deadline() {
+        let quick = sleep_cmd("0.1").spawn().unwrap().id();
+        let slow = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([quick, slow]);
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let (outcome, left) = pid_set.wait_deadline(1, deadline).unwrap();
+        assert_eq!(outcome, WaitOutcome::Exited(vec![quick]));
+        assert!(left > Duration::from_secs(1) && left < Duration::from_secs(2));
+
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let (outcome, left) = pid_set.wait_deadline(1, deadline).unwrap();
+        assert_eq!(outcome, WaitOutcome::TimedOut(vec![]));
+        assert_eq!(left, Duration::ZERO);
+        unsafe { libc::kill(slow as i32, libc::SIGKILL) };
+    }
+
+    #[test]
+    fn wait_n_timeout() {
+        let quick: Vec<PID> = (0..3)
+            .map(|_| sleep_cmd("0").spawn().unwrap().id())
+            .collect();
+        let slow = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new(quick.iter().copied().chain([slow]));
+        std::thread::sleep(Duration::from_millis(100));
+
+        // the count is reached first, and only two of the three ready exits are consumed
+        let start = Instant::now();
+        let first = pid_set.wait_n_timeout(2, Duration::from_secs(2)).unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(pid_set.len(), 2);
+
+        // the timeout fires first, returning the one exit seen before it
+        let start = Instant::now();
+        let rest = pid_set
+            .wait_n_timeout(2, Duration::from_millis(200))
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(rest.len(), 1);
+        assert!(quick.contains(&rest[0]) && !first.contains(&rest[0]));
+        assert_eq!(pid_set.len(), 1);
 
+        assert!(pid_set
+            .wait_n_timeout(1, Duration::from_millis(50))
+            .unwrap()
+            .is_empty());
+        unsafe { libc::kill(slow as i32, libc::SIGKILL) };
+        assert_eq!(pid_set.wait_n_timeout(1, Duration::MAX).unwrap(), [slow]);
+    }
+
+    #[test]
+    fn wait_coalesced() {
+        let burst: Vec<PID> = ["0", "0.1", "0.2"]
+            .into_iter()
+            .map(|secs| sleep_cmd(secs).spawn().unwrap().id())
+            .collect();
+        let slow = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new(burst.iter().copied().chain([slow]));
+
+        let start = Instant::now();
+        let mut batch = pid_set.wait_coalesced(Duration::from_millis(500)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        batch.sort_unstable();
+        let mut burst = burst;
+        burst.sort_unstable();
+        assert_eq!(batch, burst);
+        assert_eq!(pid_set.active_pids().collect::<Vec<_>>(), [slow]);
+
+        unsafe { libc::kill(slow as i32, libc::SIGKILL) };
+        assert_eq!(pid_set.wait_coalesced(Duration::ZERO).unwrap(), [slow]);
+        assert!(pid_set.wait_coalesced(Duration::ZERO).unwrap().is_empty());
+
+        // an unbounded window returns once the set drains
+        let pids: Vec<PID> = ["0", "0.1"]
+            .into_iter()
+            .map(|secs| sleep_cmd(secs).spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.iter().copied());
+        assert_eq!(pid_set.wait_coalesced(Duration::MAX).unwrap().len(), 2);
+        assert!(pid_set.is_empty());
+    }
+
+    #[test]
+    fn wait_any_or_fd() {
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        assert!(eventfd >= 0);
+        let quick = sleep_cmd("0.1").spawn().unwrap().id();
+        let slow = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([quick, slow]);
+
+        assert_eq!(
+            pid_set.wait_any_or_fd(eventfd).unwrap(),
+            InterruptOutcome::Exited(quick)
+        );
+
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            let one: u64 = 1;
+            unsafe { libc::write(eventfd, &one as *const u64 as *const libc::c_void, 8) };
+        });
+        assert_eq!(
+            pid_set.wait_any_or_fd(eventfd).unwrap(),
+            InterruptOutcome::Interrupted
+        );
+        writer.join().unwrap();
+        // the interrupt is not consumed
+        assert_eq!(
+            pid_set.wait_any_or_fd(eventfd).unwrap(),
+            InterruptOutcome::Interrupted
+        );
+        assert_eq!(pid_set.len(), 1);
+
+        unsafe { libc::close(eventfd) };
+        unsafe { libc::kill(slow as i32, libc::SIGKILL) };
+        assert_eq!(pid_set.wait_n(1).unwrap(), [slow]);
+    }
+
+    #[test]
+    fn supervise() {
+        let workers: Vec<PID> = (0..2)
+            .map(|_| sleep_cmd("0").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(workers.clone());
+        let mut exited = Vec::new();
+        let mut restarts = 0;
+        pid_set
+            .supervise(|pid| {
+                exited.push(pid);
+                restarts += 1;
+                (restarts <= 3).then(|| sleep_cmd("0").spawn().unwrap().id())
+            })
+            .unwrap();
+        assert!(pid_set.is_empty());
+        assert_eq!(exited.len(), 5);
+        assert!(workers.iter().all(|pid| exited.contains(pid)));
+        // nothing to supervise
+        pid_set.supervise(|_| unreachable!()).unwrap();
+
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        assert!(eventfd >= 0);
+        let slow = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([sleep_cmd("0").spawn().unwrap().id(), slow]);
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            let one: u64 = 1;
+            unsafe { libc::write(eventfd, &one as *const u64 as *const libc::c_void, 8) };
+        });
+        let mut respawned = None;
+        pid_set
+            .supervise_until(eventfd, |_| {
+                respawned = Some(sleep_cmd("5").spawn().unwrap().id());
+                respawned
+            })
+            .unwrap();
+        writer.join().unwrap();
+        let respawned = respawned.unwrap();
+        let mut running: Vec<PID> = pid_set.active_pids().collect();
+        running.sort_unstable();
+        let mut expected = [slow, respawned];
+        expected.sort_unstable();
+        assert_eq!(running, expected);
+
+        unsafe { libc::close(eventfd) };
+        for pid in [slow, respawned] {
+            unsafe { libc::kill(pid as i32, libc::SIGKILL) };
+        }
+        pid_set.wait_all().unwrap();
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        let pids: Vec<PID> = (0..3)
+            .map(|_| sleep_cmd("0").spawn().unwrap().id())
+            .collect();
+        let slow = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new(pids.iter().copied().chain([slow]));
+        assert_eq!(pid_set.stats(), Stats::default());
+        std::thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(pid_set.wait_n(3).unwrap().len(), 3);
+        let stats = pid_set.stats();
+        assert!(stats.waits >= 1);
+        assert_eq!(stats.events, 3);
+
+        // a timeout is a wakeup without events
+        let outcome = pid_set
+            .wait_any_timeout(Duration::from_millis(100))
+            .unwrap();
+        assert_eq!(outcome, WaitOutcome::TimedOut(vec![]));
+        let stats = pid_set.stats();
+        assert_eq!(stats.events, 3);
+        assert!(stats.total_wait_time >= Duration::from_millis(100));
+
+        assert_eq!(pid_set.reset_stats(), stats);
+        assert_eq!(pid_set.stats(), Stats::default());
+        unsafe { libc::kill(slow as i32, libc::SIGKILL) };
+        assert_eq!(pid_set.wait_n(1).unwrap(), [slow]);
+        assert_eq!(pid_set.stats().events, 1);
+    }
+
+    #[test]
+    fn registered_at() {
+        let first = sleep_cmd("0.1").spawn().unwrap().id();
+        let before = Instant::now();
+        let mut pid_set = PidSet::new([first]);
+        assert!(pid_set.registered_at(first).unwrap() >= before);
+
+        std::thread::sleep(Duration::from_millis(50));
+        let second = sleep_cmd("0.1").spawn().unwrap().id();
+        pid_set.insert(second).unwrap();
+        assert!(pid_set.age(first).unwrap() >= Duration::from_millis(50));
+        assert!(pid_set.age(second).unwrap() < pid_set.age(first).unwrap());
+        assert_eq!(pid_set.len(), 2);
+
+        assert!(pid_set.wait_all().is_ok());
+        assert_eq!(pid_set.registered_at(first), None);
+        assert_eq!(pid_set.age(second), None);
+    }
+
+    #[test]
+    fn try_clone() {
+        let first = sleep_cmd("0.1").spawn().unwrap().id();
+        let second = sleep_cmd("0.2").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([first, second]);
+        pid_set.init().unwrap();
+        pid_set.retag(first, 7).unwrap();
+
+        let mut clone = pid_set.try_clone().unwrap();
+        assert_eq!(clone.tag(first), Some(7));
+        assert_eq!(clone.len(), 2);
+        assert_ne!(clone.fd_pids[&first].0, pid_set.fd_pids[&first].0);
+
+        assert_eq!(pid_set.wait_n(1).unwrap(), [first]);
+        assert!(pid_set.close().is_ok());
+
+        // the original set closing its pidfds does not affect the clone
+        let mut exited = clone.wait_n(2).unwrap();
+        exited.sort_by_key(|pid| *pid != first);
+        assert_eq!(exited, [first, second]);
+        assert!(clone.close().is_ok());
+
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let pid_set = PidSet::new([pid]);
+        let mut clone = pid_set.try_clone().unwrap();
+        assert!(!clone.fd_pids[&pid].is_open());
+        assert!(clone.wait_all().is_ok());
+    }
+
+    #[test]
+    fn proc_available() {
+        assert!(super::proc_available());
+    }
+
+    #[test]
+    fn init_step() {
+        let pids: Vec<PID> = (0..5)
+            .map(|_| sleep_cmd("0.1").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::builder().init_chunk_size(2).build(pids);
+        let pinned = |pid_set: &PidSet| pid_set.fd_pids.values().filter(|fd| fd.is_open()).count();
+
+        assert!(!pid_set.init_step().unwrap());
+        assert_eq!(pinned(&pid_set), 2);
+        assert!(!pid_set.init_step().unwrap());
+        assert_eq!(pinned(&pid_set), 4);
+        assert!(pid_set.epoll_fd.is_none());
+        assert!(pid_set.init_step().unwrap());
+        assert!(pid_set.epoll_fd.is_some());
+        assert!(pid_set.init_step().unwrap());
+        assert!(pid_set.wait_all().is_ok());
+
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        assert!(pid_set.init_step().unwrap());
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn verify_pid() {
+        let labeled = sleep_cmd("0.2").spawn().unwrap().id();
+        let actual = sleep_cmd("0.2").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([labeled]);
+        assert!(pid_set.verify_pid(labeled).is_ok());
+        pid_set.init().unwrap();
+        assert!(pid_set.verify_pid(labeled).is_ok());
+        assert!(matches!(
+            pid_set.verify_pid(actual),
+            Err(PidSetError::PidNotFound(_))
+        ));
+
+        // mislabel the pidfd of `actual` as `labeled`
+        let PidFd(fd) = pidfd_open(Pid(actual)).unwrap();
+        let pidfd = unsafe { OwnedFd::from_raw_fd(fd) };
+        pid_set.add_pidfd(pidfd, Some(labeled)).unwrap();
+        let Err(PidSetError::PidMismatch { pid, actual: found }) = pid_set.verify_pid(labeled)
+        else {
+            panic!("mislabeled pidfd not detected");
+        };
+        assert_eq!((pid, found), (labeled, actual));
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn wait_any_with_ready() {
+        let pids: Vec<PID> = (0..3)
+            .map(|_| Command::new("true").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids);
+        pid_set.init().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+
+        assert!(matches!(pid_set.wait_any_with_ready(), Ok(Some((_, 2)))));
+        assert_eq!(pid_set.len(), 2);
+        assert!(matches!(pid_set.wait_any_with_ready(), Ok(Some((_, 1)))));
+        assert!(matches!(pid_set.wait_any_with_ready(), Ok(Some((_, 0)))));
+        assert!(matches!(pid_set.wait_any_with_ready(), Ok(None)));
+    }
+
+    #[test]
+    fn wait_any_consumes_one() {
+        let pids: Vec<PID> = (0..3)
+            .map(|_| Command::new("true").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids);
+        pid_set.init().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+
+        for remaining in (0..3).rev() {
+            pid_set.wait_any().unwrap();
+            assert_eq!(pid_set.len(), remaining);
+        }
         assert!(pid_set.wait_any().is_ok());
-        assert!(
-            start_time.elapsed() < Duration::from_secs(3),
-            "Expected wait_any() to return in less than 3 seconds, but it took {:?}",
-            start_time.elapsed()
+        assert_eq!(pid_set.exited(), 3);
+    }
+
+    #[test]
+    fn enforce_deadline() {
+        let old = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([old]);
+        std::thread::sleep(Duration::from_millis(100));
+        let young = sleep_cmd("5").spawn().unwrap().id();
+        pid_set.insert(young).unwrap();
+
+        let signaled = pid_set
+            .enforce_deadline(Duration::from_millis(50), libc::SIGKILL)
+            .unwrap();
+        assert_eq!(signaled, [old]);
+        assert_eq!(pid_set.wait_n(1).unwrap(), [old]);
+        assert!(pid_set.has_exited(young).is_ok_and(|exited| !exited));
+
+        let signaled = pid_set
+            .enforce_deadline(Duration::ZERO, libc::SIGKILL)
+            .unwrap();
+        assert_eq!(signaled, [young]);
+        assert!(pid_set.wait_all().is_ok());
+    }
+
+    #[test]
+    fn export_for_exec() {
+        let cloexec = |fd: FD| unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC != 0;
+        let pids: Vec<PID> = (0..2)
+            .map(|_| sleep_cmd("0.1").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.clone());
+
+        let handoff = pid_set.export_for_exec().unwrap();
+        let fds: Vec<FD> = pid_set.fd_pids.values().map(|fd| fd.0).collect();
+        assert!(fds.iter().all(|fd| !cloexec(*fd)));
+        // hand the descriptors over as an exec would, without closing them
+        std::mem::forget(pid_set);
+
+        let mut inherited = PidSet::from_inherited(&handoff).unwrap();
+        assert!(fds.iter().all(|fd| cloexec(*fd)));
+        let mut exited = inherited.wait_n(2).unwrap();
+        exited.sort();
+        let mut expected = pids;
+        expected.sort();
+        assert_eq!(exited, expected);
+        assert!(inherited.close().is_ok());
+
+        for handoff in ["", "x", "3 1:", "3 a:4"] {
+            assert!(matches!(
+                PidSet::from_inherited(handoff),
+                Err(PidSetError::InvalidHandoff(_))
+            ));
+        }
+        assert!(matches!(
+            PidSet::from_inherited(&format!("{}", i32::MAX)),
+            Err(PidSetError::InvalidHandoff(_))
+        ));
+    }
+
+    #[test]
+    fn try_wait_any_spin() {
+        let pid = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        assert!(matches!(pid_set.try_wait_any_spin(0), Ok(None)));
+        assert!(matches!(pid_set.try_wait_any_spin(100), Ok(None)));
+
+        unsafe { libc::kill(pid as i32, libc::SIGKILL) };
+        let mut exited = None;
+        while exited.is_none() {
+            exited = pid_set.try_wait_any_spin(1000).unwrap();
+        }
+        assert_eq!(exited, Some(pid));
+        assert!(pid_set.is_empty());
+    }
+
+    #[test]
+    fn wait_into() {
+        let mut pid_set = PidSet::new(std::iter::empty());
+        let mut out = Vec::with_capacity(4);
+        assert_eq!(pid_set.wait_into(1, &mut out).unwrap(), 0);
+
+        for _ in 0..2 {
+            let mut pids: Vec<PID> = (0..3)
+                .map(|_| sleep_cmd("0.1").spawn().unwrap().id())
+                .collect();
+            for pid in &pids {
+                pid_set.insert(*pid).unwrap();
+            }
+            pids.sort();
+            out.clear();
+            out.push(0);
+            assert_eq!(pid_set.wait_into(2, &mut out).unwrap(), 2);
+            assert_eq!(pid_set.wait_into(5, &mut out).unwrap(), 1);
+            assert_eq!(out[0], 0, "existing contents are kept");
+            out[1..].sort();
+            assert_eq!(out[1..], pids);
+            assert_eq!(out.capacity(), 4);
+        }
+    }
+
+    #[test]
+    fn add_fd() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_end, write_end] = fds;
+        let pid = sleep_cmd("0.3").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        pid_set.add_fd(read_end, 42).unwrap();
+        assert!(pid_set.add_fd(read_end, 42).is_err());
+        assert_eq!(pid_set.len(), 1);
+
+        assert!(pid_set
+            .wait_events(Some(Duration::from_millis(10)))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            unsafe { libc::write(write_end, b"x".as_ptr().cast(), 1) },
+            1
         );
+        let events = pid_set.wait_events(Some(Duration::MAX)).unwrap();
+        assert_eq!(
+            events,
+            [PidSetEvent::Fd {
+                token: 42,
+                events: EPOLLIN as u32
+            }]
+        );
+
+        // readiness seen by another wait is kept for the next wait_events
+        unsafe { libc::close(write_end) };
+        pid_set.wait_all().unwrap();
+        let events = pid_set.wait_events(None).unwrap();
+        let [PidSetEvent::Fd { token: 42, events }] = events[..] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert!(events & libc::EPOLLHUP as u32 != 0);
+
+        pid_set.remove_fd(read_end).unwrap();
+        assert!(pid_set.remove_fd(read_end).is_err());
+        assert!(pid_set.wait_events(None).unwrap().is_empty());
+        unsafe { libc::close(read_end) };
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn unexpected_events() {
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLPRI as u32,
+            u64: pid as u64,
+        };
+        let Err(PidSetError::UnexpectedEvents {
+            pid: reported,
+            events,
+        }) = pid_set.process_events(epoll_fd, &mut [event])
+        else {
+            panic!("EPOLLPRI taken for an exit");
+        };
+        assert_eq!((reported, events), (pid, libc::EPOLLPRI as u32));
+        assert!(pid_set.is_empty(), "the PID is no longer monitored");
+        assert_eq!(pid_set.exited(), 0);
+
+        // fd-level failures, even along with EPOLLIN
+        for events in [
+            libc::EPOLLERR,
+            libc::EPOLLHUP,
+            EPOLLIN | libc::EPOLLERR,
+            libc::EPOLLERR | libc::EPOLLHUP,
+        ] {
+            let pid = sleep_cmd("0.1").spawn().unwrap().id();
+            let mut pid_set = PidSet::new([pid]);
+            let epoll_fd = pid_set.init_epoll().unwrap();
+            event.events = events as u32;
+            event.u64 = pid as u64;
+            let result = pid_set.process_events(epoll_fd, &mut [event]);
+            assert!(
+                matches!(result, Err(PidSetError::FdError(reported)) if reported == pid),
+                "{events:#x}"
+            );
+            assert!(pid_set.is_empty());
+            assert_eq!(pid_set.exited(), 0);
+        }
+
+        // reaped processes report EPOLLHUP along with EPOLLIN
+        let pid = sleep_cmd("0.1").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        event.events = (EPOLLIN | libc::EPOLLHUP) as u32;
+        event.u64 = pid as u64;
+        let exits = pid_set.process_events(epoll_fd, &mut [event]).unwrap();
+        assert_eq!(exits[0].pid, pid);
+    }
+
+    #[test]
+    fn batch_exit_codes() {
+        let exit_cmd = |code: i32| {
+            std::process::Command::new("sh")
+                .args(["-c", &format!("sleep 0.2; exit {code}")])
+                .spawn()
+                .unwrap()
+                .id()
+        };
+        let children: Vec<PID> = (1..=8).map(exit_cmd).collect();
+        let broken = exit_cmd(9);
+        let mut pid_set = PidSet::new(children.iter().copied().chain([broken]));
+        let epoll_fd = pid_set.init_epoll().unwrap();
+        // a descriptor that is not open fails both waitid and EPOLL_CTL_DEL
+        let pidfd = pid_set.fd_pids.insert(broken, PidFd(1 << 20)).unwrap();
+        pidfd.close();
+        let mut events: Vec<libc::epoll_event> = children
+            .iter()
+            .chain([&broken])
+            .map(|pid| libc::epoll_event {
+                events: EPOLLIN as u32,
+                u64: Pid(*pid).token(),
+            })
+            .collect();
+        // the broken PID first, not to spare any other
+        events.rotate_right(1);
+
+        std::thread::sleep(Duration::from_millis(500));
+        let exits = pid_set.process_events(epoll_fd, &mut events).unwrap();
+        assert_eq!(exits.len(), children.len() + 1);
+        assert_eq!((exits[0].pid, exits[0].code), (broken, None));
+        for (info, (code, pid)) in exits[1..].iter().zip((1..=8).zip(&children)) {
+            assert_eq!((info.pid, info.code), (*pid, Some(code)));
+        }
+        assert!(pid_set.is_empty());
+
+        let failures = pid_set.take_exit_failures();
+        assert!(pid_set.exit_failures().is_empty());
+        assert!(matches!(
+            failures.as_slice(),
+            [
+                (pid, PidSetError::WaitPid(_, status)),
+                (_, PidSetError::EpollCtl(deregistration)),
+            ] if *pid == broken
+                && status.raw_os_error() == Some(libc::EBADF)
+                && deregistration.raw_os_error() == Some(libc::EBADF)
+        ));
+        for pid in children.into_iter().chain([broken]) {
+            unsafe { libc::waitpid(pid as i32, std::ptr::null_mut(), 0) };
+        }
+    }
+
+    #[test]
+    fn batch_order() {
+        let pids: Vec<PID> = (0..4)
+            .map(|_| Command::new("true").spawn().unwrap().id())
+            .collect();
+        let mut pid_set = PidSet::new(pids.clone());
+        pid_set.init().unwrap();
+        pid_set.set_batch_order(Some(Box::new(|a, b| b.cmp(&a))));
+        // wait until every child is a zombie, so that all exits land in one batch
+        for &pid in &pids {
+            let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+            let flags = libc::WEXITED | libc::WNOWAIT;
+            assert_eq!(
+                unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, flags) },
+                0
+            );
+        }
+
+        let exited: Vec<PID> = pid_set
+            .drain_wait_all()
+            .unwrap()
+            .iter()
+            .map(|info| info.pid)
+            .collect();
+        let mut expected = pids;
+        expected.sort_by(|a, b| b.cmp(a));
+        assert_eq!(exited, expected);
+    }
+
+    #[test]
+    fn close_uninitialized() {
+        // the PID is already reaped, so any pidfd_open attempt would fail
+        let mut child = sleep_cmd("0").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+
+        let pid_set = PidSet::new([pid]);
+        assert!(pid_set.close().is_ok());
+    }
+
+    #[test]
+    fn try_wait_any() {
+        let pid = sleep_cmd("5").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        assert!(matches!(pid_set.try_wait_any(), Ok(None)));
+        assert_eq!(pid_set.len(), 1);
+
+        let pid = sleep_cmd("0").spawn().unwrap().id();
+        let mut pid_set = PidSet::new([pid]);
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(pid_set.try_wait_any().unwrap(), Some(pid));
+        assert!(pid_set.is_empty());
     }
 }
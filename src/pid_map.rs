@@ -0,0 +1,251 @@
+//! The map of monitored PIDs to their pidfd, stored inline while it is small.
+
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    ops::Index,
+};
+
+use crate::{PidFd, PID};
+
+/// How many PIDs are stored inline before spilling to a `HashMap`.
+pub(crate) const INLINE_CAPACITY: usize = 8;
+
+/// A map of process IDs (PIDs) to their associated file descriptors.
+///
+/// Up to [`INLINE_CAPACITY`] entries live in an array, looked up by a linear
+/// scan, so a set of a handful of PIDs never allocates for its map.
+/// Inserting one more moves every entry to the `HashMap`, which is used until
+/// it is emptied again; the array is only in use while the `HashMap` is empty.
+pub(crate) struct PidMap<S = RandomState> {
+    inline: [(PID, PidFd); INLINE_CAPACITY],
+    inline_len: usize,
+    hashed: HashMap<PID, PidFd, S>,
+}
+
+impl PidMap {
+    pub(crate) fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<S> PidMap<S> {
+    /// Creates an empty map, which does not allocate until it spills.
+    pub(crate) fn with_hasher(hasher: S) -> Self {
+        Self {
+            inline: [(0, PidFd::UNOPENED); INLINE_CAPACITY],
+            inline_len: 0,
+            hashed: HashMap::with_hasher(hasher),
+        }
+    }
+
+    pub(crate) fn hasher(&self) -> &S {
+        self.hashed.hasher()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.inline_len + self.hashed.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the entries, in arbitrary order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&PID, &PidFd)> {
+        self.inline[..self.inline_len]
+            .iter()
+            .map(|(pid, fd)| (pid, fd))
+            .chain(self.hashed.iter())
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (&PID, &mut PidFd)> {
+        self.inline[..self.inline_len]
+            .iter_mut()
+            .map(|(pid, fd)| (&*pid, fd))
+            .chain(self.hashed.iter_mut())
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &PID> {
+        self.iter().map(|(pid, _)| pid)
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &PidFd> {
+        self.iter().map(|(_, fd)| fd)
+    }
+
+    /// Removes every entry and iterates over them.
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = (PID, PidFd)> + '_ {
+        let inline_len = std::mem::take(&mut self.inline_len);
+        self.inline
+            .into_iter()
+            .take(inline_len)
+            .chain(self.hashed.drain())
+    }
+
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&PID, &mut PidFd) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.inline_len {
+            let (pid, mut fd) = self.inline[i];
+            if keep(&pid, &mut fd) {
+                self.inline[kept] = (pid, fd);
+                kept += 1;
+            }
+        }
+        self.inline_len = kept;
+        self.hashed.retain(keep);
+    }
+
+    fn inline_position(&self, pid: PID) -> Option<usize> {
+        self.inline[..self.inline_len]
+            .iter()
+            .position(|(inline_pid, _)| *inline_pid == pid)
+    }
+}
+
+impl<S: BuildHasher> PidMap<S> {
+    pub(crate) fn get(&self, pid: &PID) -> Option<&PidFd> {
+        if self.hashed.is_empty() {
+            self.inline_position(*pid).map(|i| &self.inline[i].1)
+        } else {
+            self.hashed.get(pid)
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, pid: &PID) -> Option<&mut PidFd> {
+        if self.hashed.is_empty() {
+            self.inline_position(*pid).map(|i| &mut self.inline[i].1)
+        } else {
+            self.hashed.get_mut(pid)
+        }
+    }
+
+    pub(crate) fn contains_key(&self, pid: &PID) -> bool {
+        self.get(pid).is_some()
+    }
+
+    /// Inserts `fd` for `pid`, returning the fd it replaces if any.
+    pub(crate) fn insert(&mut self, pid: PID, fd: PidFd) -> Option<PidFd> {
+        if !self.hashed.is_empty() {
+            return self.hashed.insert(pid, fd);
+        }
+        if let Some(i) = self.inline_position(pid) {
+            return Some(std::mem::replace(&mut self.inline[i].1, fd));
+        }
+        if self.inline_len < INLINE_CAPACITY {
+            self.inline[self.inline_len] = (pid, fd);
+            self.inline_len += 1;
+        } else {
+            self.hashed.reserve(INLINE_CAPACITY + 1);
+            let inline_len = std::mem::take(&mut self.inline_len);
+            self.hashed.extend(self.inline.into_iter().take(inline_len));
+            self.hashed.insert(pid, fd);
+        }
+        None
+    }
+
+    pub(crate) fn remove(&mut self, pid: &PID) -> Option<PidFd> {
+        if !self.hashed.is_empty() {
+            return self.hashed.remove(pid);
+        }
+        let i = self.inline_position(*pid)?;
+        let (_, fd) = self.inline[i];
+        self.inline.copy_within(i + 1..self.inline_len, i);
+        self.inline_len -= 1;
+        Some(fd)
+    }
+
+    /// Reserves room for `additional` more entries, spilling right away if they
+    /// would not all fit inline.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        if self.hashed.is_empty() && self.inline_len + additional <= INLINE_CAPACITY {
+            return;
+        }
+        self.hashed.reserve(self.inline_len + additional);
+        let inline_len = std::mem::take(&mut self.inline_len);
+        self.hashed.extend(self.inline.into_iter().take(inline_len));
+    }
+}
+
+impl<S: BuildHasher> Extend<(PID, PidFd)> for PidMap<S> {
+    fn extend<I: IntoIterator<Item = (PID, PidFd)>>(&mut self, entries: I) {
+        let entries = entries.into_iter();
+        self.reserve(entries.size_hint().0);
+        for (pid, fd) in entries {
+            self.insert(pid, fd);
+        }
+    }
+}
+
+impl<S: BuildHasher> Index<&PID> for PidMap<S> {
+    type Output = PidFd;
+
+    fn index(&self, pid: &PID) -> &PidFd {
+        self.get(pid).expect("PID is in the map")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn sorted(map: &PidMap) -> Vec<(PID, PidFd)> {
+        let mut entries: Vec<(PID, PidFd)> = map.iter().map(|(pid, fd)| (*pid, *fd)).collect();
+        entries.sort_unstable_by_key(|(pid, _)| *pid);
+        entries
+    }
+
+    #[test]
+    fn spill_and_shrink() {
+        let mut map = PidMap::new();
+        let count = INLINE_CAPACITY as PID + 2;
+        for pid in 1..=count {
+            assert_eq!(map.insert(pid, PidFd(pid as i32)), None);
+            assert_eq!(map.hashed.is_empty(), pid as usize <= INLINE_CAPACITY);
+        }
+        assert_eq!(map.insert(1, PidFd(100)), Some(PidFd(1)));
+        assert_eq!(map.len(), count as usize);
+        assert_eq!(map[&1], PidFd(100));
+
+        for pid in 1..=count {
+            assert!(map.remove(&pid).is_some());
+        }
+        assert!(map.is_empty());
+        // back to the array once the `HashMap` is emptied
+        map.insert(1, PidFd(1));
+        assert!(map.hashed.is_empty());
+    }
+
+    #[test]
+    fn inline_operations() {
+        let mut map = PidMap::new();
+        map.extend((1..=5).map(|pid| (pid, PidFd(pid as i32))));
+        assert_eq!(map.remove(&2), Some(PidFd(2)));
+        assert_eq!(map.remove(&2), None);
+        *map.get_mut(&3).unwrap() = PidFd(30);
+        map.retain(|pid, fd| {
+            fd.0 += 1;
+            *pid != 4
+        });
+        assert_eq!(sorted(&map), [(1, PidFd(2)), (3, PidFd(31)), (5, PidFd(6))]);
+        assert!(map.contains_key(&5) && !map.contains_key(&4));
+
+        let drained: HashSet<PID> = map.drain().map(|(pid, _)| pid).collect();
+        assert_eq!(drained, HashSet::from([1, 3, 5]));
+        assert!(map.is_empty());
+        assert!(map.get(&1).is_none());
+    }
+
+    #[test]
+    fn reserve_spills() {
+        let mut map = PidMap::new();
+        map.insert(1, PidFd(1));
+        map.reserve(2);
+        assert!(map.hashed.is_empty());
+        map.reserve(INLINE_CAPACITY);
+        assert_eq!(sorted(&map), [(1, PidFd(1))]);
+        assert!(!map.hashed.is_empty());
+    }
+}
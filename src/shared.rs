@@ -0,0 +1,236 @@
+//! A PID set that can be waited on from several threads at once.
+
+use std::sync::Mutex;
+
+use libc::{EPOLLIN, EPOLLONESHOT};
+
+use crate::{
+    epoll_add, epoll_add_pidfd, epoll_del, pidfd_open, syserr, FDPidsMap, Pid, PidSetError, FD, PID,
+};
+
+/// Token reserved for the eventfd used to wake every waiter once the set is drained.
+///
+/// PIDs are `u32`, so no pidfd token can ever collide with it.
+const DRAINED_TOKEN: u64 = u64::MAX;
+
+/// A set of PIDs that several threads can drain cooperatively.
+///
+/// Every pidfd is registered with `EPOLLONESHOT`, so the kernel hands each exit to
+/// a single `epoll_wait` caller, and the PID is then claimed by removing it from
+/// the set under a short-lived lock. Each exited PID is therefore returned by
+/// exactly one call to [`SharedPidSet::wait_any`], whichever thread makes it.
+///
+/// Unlike [`PidSet`](crate::PidSet), registration is eager: all pidfds are opened in
+/// [`SharedPidSet::new`].
+///
+/// `SharedPidSet` is [`Send`] and [`Sync`]: every method takes `&self`, and the
+/// PID map is the only mutable state, behind a `Mutex`.
+///
+/// # Examples
+///
+/// ```rust
+/// use pid_set::{PidSetError, SharedPidSet};
+///
+/// fn main() -> Result<(), PidSetError> {
+///     let pids = (0..4).map(|_| std::process::Command::new("true").spawn().unwrap().id());
+///     let set = SharedPidSet::new(pids)?;
+///
+///     std::thread::scope(|s| {
+///         for _ in 0..2 {
+///             s.spawn(|| while let Ok(Some(_pid)) = set.wait_any() {});
+///         }
+///     });
+///
+///     assert!(set.is_empty());
+///     set.close()
+/// }
+/// ```
+pub struct SharedPidSet {
+    fd_pids: Mutex<FDPidsMap>,
+    epoll_fd: Option<FD>,
+    drained_fd: Option<FD>,
+}
+
+impl SharedPidSet {
+    /// Creates a new `SharedPidSet`, opening and registering a pidfd for every PID.
+    ///
+    /// # Arguments
+    ///
+    /// * `pids` - An iterator over the PIDs to monitor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if the epoll instance, the eventfd or any pidfd cannot
+    /// be created or registered.
+    pub fn new<P: IntoIterator<Item = PID>>(pids: P) -> Result<Self, PidSetError> {
+        let mut set = Self {
+            fd_pids: Mutex::new(FDPidsMap::new()),
+            epoll_fd: None,
+            drained_fd: None,
+        };
+        let epoll_fd =
+            unsafe { syserr(libc::epoll_create1(0)) }.map_err(PidSetError::EpollCreate)?;
+        set.epoll_fd = Some(epoll_fd);
+
+        let drained_fd =
+            unsafe { syserr(libc::eventfd(0, libc::EFD_CLOEXEC)) }.map_err(PidSetError::EventFd)?;
+        set.drained_fd = Some(drained_fd);
+        epoll_add(epoll_fd, drained_fd, DRAINED_TOKEN, EPOLLIN as u32)?;
+
+        let fd_pids = set.fd_pids.get_mut().unwrap();
+        for pid in pids {
+            let fd = pidfd_open(Pid(pid))?;
+            if let Some(previous) = fd_pids.insert(pid, fd) {
+                previous.close();
+            }
+        }
+        for (pid, fd) in fd_pids.iter() {
+            epoll_add_pidfd(epoll_fd, *fd, Pid(*pid), (EPOLLIN | EPOLLONESHOT) as u32)?;
+        }
+        if fd_pids.is_empty() {
+            set.signal_drained()?;
+        }
+        Ok(set)
+    }
+
+    /// Blocks until one monitored PID exits and returns it.
+    ///
+    /// Returns `None` once every PID has been claimed, including for threads that
+    /// were already blocked when the last PID was handed to another thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_any(&self) -> Result<Option<PID>, PidSetError> {
+        let epoll_fd = self.epoll_fd.expect("epoll is initialized on creation");
+        loop {
+            let mut event = libc::epoll_event { events: 0, u64: 0 };
+            let waited = syserr(unsafe { libc::epoll_wait(epoll_fd, &mut event, 1, -1) });
+            let event_count = match waited {
+                Ok(event_count) => event_count,
+                // woken up by a signal
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(PidSetError::EpollWait(err)),
+            };
+            if event_count == 0 {
+                continue;
+            }
+            if event.u64 == DRAINED_TOKEN {
+                return Ok(None);
+            }
+
+            let Some(Pid(pid)) = Pid::from_token(event.u64) else {
+                continue;
+            };
+            let mut fd_pids = self.fd_pids.lock().unwrap();
+            let Some(fd) = fd_pids.remove(&pid) else {
+                // already claimed by another waiter
+                continue;
+            };
+            let drained = fd_pids.is_empty();
+            drop(fd_pids);
+
+            epoll_del(epoll_fd, fd)?;
+            fd.close();
+            if drained {
+                self.signal_drained()?;
+            }
+            return Ok(Some(pid));
+        }
+    }
+
+    /// Returns the number of PIDs that have not been claimed yet.
+    pub fn len(&self) -> usize {
+        self.fd_pids.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the epoll, eventfd and remaining pidfd file descriptors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs while closing the epoll file descriptor.
+    pub fn close(mut self) -> Result<(), PidSetError> {
+        self.release()
+    }
+
+    fn signal_drained(&self) -> Result<(), PidSetError> {
+        let drained_fd = self.drained_fd.expect("eventfd is initialized on creation");
+        let value: u64 = 1;
+        unsafe {
+            syserr(libc::write(
+                drained_fd,
+                &value as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            ) as libc::c_int)
+        }
+        .map_err(PidSetError::EventFd)?;
+        Ok(())
+    }
+
+    fn release(&mut self) -> Result<(), PidSetError> {
+        for (_, fd) in self.fd_pids.get_mut().unwrap().drain() {
+            fd.close();
+        }
+        if let Some(drained_fd) = self.drained_fd.take() {
+            unsafe { libc::close(drained_fd) };
+        }
+        if let Some(epoll_fd) = self.epoll_fd.take() {
+            unsafe { syserr(libc::close(epoll_fd)) }.map_err(PidSetError::EpollClose)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SharedPidSet {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn wait_any_from_many_threads() {
+        let pids: Vec<PID> = (0..200)
+            .map(|_| std::process::Command::new("true").spawn().unwrap().id())
+            .collect();
+        let set = SharedPidSet::new(pids.clone()).unwrap();
+
+        let claimed: Vec<PID> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut claimed = Vec::new();
+                        while let Some(pid) = set.wait_any().unwrap() {
+                            claimed.push(pid);
+                        }
+                        claimed
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        assert_eq!(
+            claimed.len(),
+            pids.len(),
+            "every exit is claimed exactly once"
+        );
+        assert_eq!(
+            claimed.into_iter().collect::<HashSet<_>>(),
+            pids.into_iter().collect::<HashSet<_>>()
+        );
+        assert!(set.is_empty());
+        assert!(set.close().is_ok());
+    }
+}
@@ -0,0 +1,271 @@
+//! A `SIGCHLD`-based alternative to pidfd for kernels without `pidfd_open`.
+
+use std::collections::{HashSet, VecDeque};
+
+use libc::EPOLLIN;
+
+use crate::{epoll_add, syserr, to_pid_t, ExitInfo, Pid, PidSetError, FD, PID};
+
+/// Upper bound, in milliseconds, between two scans of the monitored children.
+///
+/// A `SIGCHLD` can be consumed by a thread that does not block it, in which
+/// case the signalfd never becomes readable; the periodic rescan bounds how long
+/// such an exit goes unnoticed.
+const RESCAN_INTERVAL_MS: i32 = 100;
+
+/// Monitors direct children through a signalfd for `SIGCHLD` instead of pidfds.
+///
+/// This is meant for kernels without `pidfd_open` (before 5.3). On every
+/// `SIGCHLD`, the monitored PIDs are reaped with `waitpid(WNOHANG)`; other
+/// children of the process are left untouched.
+///
+/// Only direct children of the calling process can be monitored, and they are
+/// reaped as they are reported, so their exit status is always available.
+///
+/// [`SignalFdSet::new`] blocks `SIGCHLD` in the calling thread and leaves it
+/// blocked. For timely notifications `SIGCHLD` should be blocked in every
+/// thread, e.g. by creating the set before spawning any threads. The set can be
+/// moved to another thread, that thread then needs `SIGCHLD` blocked as well.
+pub struct SignalFdSet {
+    pids: HashSet<PID>,
+    exited: VecDeque<ExitInfo>,
+    epoll_fd: Option<FD>,
+    signal_fd: Option<FD>,
+}
+
+impl SignalFdSet {
+    /// Creates a new `SignalFdSet` monitoring the specified child PIDs.
+    ///
+    /// # Arguments
+    ///
+    /// * `pids` - An iterator over the child PIDs to monitor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError::InvalidPid` if a PID is out of the `pid_t` range, or
+    /// `PidSetError` if `SIGCHLD` cannot be blocked or the signalfd or epoll
+    /// instance cannot be created.
+    pub fn new<P: IntoIterator<Item = PID>>(pids: P) -> Result<Self, PidSetError> {
+        let pids: HashSet<PID> = pids.into_iter().collect();
+        // waitpid on 0 or a negative pid would reap unrelated children
+        for pid in &pids {
+            to_pid_t(Pid(*pid))?;
+        }
+        let mut set = Self {
+            pids,
+            exited: VecDeque::new(),
+            epoll_fd: None,
+            signal_fd: None,
+        };
+
+        let mut mask: libc::sigset_t = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::sigemptyset(&mut mask);
+            libc::sigaddset(&mut mask, libc::SIGCHLD);
+        }
+        let res = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut()) };
+        if res != 0 {
+            return Err(PidSetError::SigMask(std::io::Error::from_raw_os_error(res)));
+        }
+
+        let signal_fd = unsafe {
+            syserr(libc::signalfd(
+                -1,
+                &mask,
+                libc::SFD_NONBLOCK | libc::SFD_CLOEXEC,
+            ))
+        }
+        .map_err(PidSetError::SignalFd)?;
+        set.signal_fd = Some(signal_fd);
+
+        let epoll_fd =
+            unsafe { syserr(libc::epoll_create1(0)) }.map_err(PidSetError::EpollCreate)?;
+        set.epoll_fd = Some(epoll_fd);
+        epoll_add(epoll_fd, signal_fd, 0, EPOLLIN as u32)?;
+        Ok(set)
+    }
+
+    /// Waits for any one monitored child to exit, reaps it and returns its status.
+    ///
+    /// Returns `None` if there are no children left to wait for.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait, or
+    /// `PidSetError::WaitPid` if a monitored PID is not a child of the caller.
+    pub fn wait_any(&mut self) -> Result<Option<ExitInfo>, PidSetError> {
+        let epoll_fd = self.epoll_fd.expect("epoll is initialized on creation");
+        loop {
+            if let Some(info) = self.exited.pop_front() {
+                return Ok(Some(info));
+            }
+            if self.pids.is_empty() {
+                return Ok(None);
+            }
+            // scan first: the SIGCHLD may have been delivered before the wait
+            self.reap()?;
+            if !self.exited.is_empty() {
+                continue;
+            }
+
+            let mut event = libc::epoll_event { events: 0, u64: 0 };
+            let waited =
+                syserr(unsafe { libc::epoll_wait(epoll_fd, &mut event, 1, RESCAN_INTERVAL_MS) });
+            if let Err(err) = waited {
+                // woken up by a signal, the next scan catches up on any exit
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(PidSetError::EpollWait(err));
+                }
+                continue;
+            }
+            self.drain_signals()?;
+        }
+    }
+
+    /// Waits for all monitored children to exit and returns their statuses.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs during the wait.
+    pub fn wait_all(&mut self) -> Result<Vec<ExitInfo>, PidSetError> {
+        let mut infos = Vec::with_capacity(self.len());
+        while let Some(info) = self.wait_any()? {
+            infos.push(info);
+        }
+        Ok(infos)
+    }
+
+    /// Returns the number of children that have not been reported yet.
+    pub fn len(&self) -> usize {
+        self.pids.len() + self.exited.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Closes the signalfd and epoll file descriptors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PidSetError` if an error occurs while closing the epoll file descriptor.
+    pub fn close(mut self) -> Result<(), PidSetError> {
+        self.release()
+    }
+
+    /// Reaps every monitored child that has exited, queueing their statuses.
+    fn reap(&mut self) -> Result<(), PidSetError> {
+        let mut reaped = Vec::new();
+        for pid in &self.pids {
+            let mut status: libc::c_int = 0;
+            let res = unsafe { libc::waitpid(*pid as libc::pid_t, &mut status, libc::WNOHANG) };
+            if res < 0 {
+                return Err(PidSetError::WaitPid(*pid, std::io::Error::last_os_error()));
+            }
+            if res == 0 {
+                continue;
+            }
+            reaped.push(
+                ExitInfo::from_wait_status(*pid, status, true).unwrap_or(ExitInfo {
+                    is_child: true,
+                    ..ExitInfo::unknown(*pid)
+                }),
+            );
+        }
+        for info in reaped {
+            self.pids.remove(&info.pid);
+            self.exited.push_back(info);
+        }
+        Ok(())
+    }
+
+    /// Consumes the pending `SIGCHLD` notifications so the signalfd stops being readable.
+    fn drain_signals(&mut self) -> Result<(), PidSetError> {
+        let signal_fd = self.signal_fd.expect("signalfd is initialized on creation");
+        let mut info: libc::signalfd_siginfo = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::signalfd_siginfo>();
+        loop {
+            let res =
+                unsafe { libc::read(signal_fd, &mut info as *mut _ as *mut libc::c_void, size) };
+            if res < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::WouldBlock {
+                    return Ok(());
+                }
+                return Err(PidSetError::SignalFd(err));
+            }
+        }
+    }
+
+    fn release(&mut self) -> Result<(), PidSetError> {
+        if let Some(signal_fd) = self.signal_fd.take() {
+            unsafe { libc::close(signal_fd) };
+        }
+        if let Some(epoll_fd) = self.epoll_fd.take() {
+            unsafe { syserr(libc::close(epoll_fd)) }.map_err(PidSetError::EpollClose)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SignalFdSet {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit_cmd(code: i32) -> PID {
+        std::process::Command::new("sh")
+            .args(["-c", &format!("sleep 0.1; exit {code}")])
+            .spawn()
+            .unwrap()
+            .id()
+    }
+
+    #[test]
+    fn wait_all_children() {
+        let pids = [exit_cmd(0), exit_cmd(1), exit_cmd(2)];
+        let mut set = SignalFdSet::new(pids).unwrap();
+
+        let mut infos = set.wait_all().unwrap();
+        infos.sort_by_key(|info| info.pid);
+        let mut expected: Vec<ExitInfo> = pids
+            .iter()
+            .zip(0..)
+            .map(|(pid, code)| ExitInfo {
+                pid: *pid,
+                code: Some(code),
+                signal: None,
+                is_child: true,
+            })
+            .collect();
+        expected.sort_by_key(|info| info.pid);
+
+        assert_eq!(infos, expected);
+        assert!(set.is_empty());
+        assert!(matches!(set.wait_any(), Ok(None)));
+        assert!(set.close().is_ok());
+    }
+
+    #[test]
+    fn invalid_pid() {
+        assert!(matches!(
+            SignalFdSet::new([0]),
+            Err(PidSetError::InvalidPid(0))
+        ));
+        assert!(matches!(
+            SignalFdSet::new([u32::MAX]),
+            Err(PidSetError::InvalidPid(u32::MAX))
+        ));
+    }
+
+    #[test]
+    fn not_a_child() {
+        let mut set = SignalFdSet::new([1]).unwrap();
+        assert!(matches!(set.wait_any(), Err(PidSetError::WaitPid(1, _))));
+    }
+}
@@ -0,0 +1,26 @@
+//! Discovery of the calling process's children.
+//!
+//! Lives in its own test binary so that no other test spawns or reaps children
+//! concurrently.
+
+use std::collections::HashSet;
+use std::process::Command;
+
+use pid_set::PidSet;
+
+#[test]
+fn all_children_discovered() {
+    let pids: HashSet<u32> = (0..3)
+        .map(|_| Command::new("sleep").arg("0.2").spawn().unwrap().id())
+        .collect();
+
+    let mut pid_set = PidSet::all_children().unwrap();
+    assert_eq!(pid_set.active_pids().collect::<HashSet<_>>(), pids);
+
+    let mut exited = pid_set.wait(pids.len()).unwrap().exited;
+    exited.sort();
+    let mut expected: Vec<u32> = pids.into_iter().collect();
+    expected.sort();
+    assert_eq!(exited, expected);
+    assert!(pid_set.close().is_ok());
+}
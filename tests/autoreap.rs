@@ -0,0 +1,36 @@
+//! Exit monitoring when the kernel reaps children automatically.
+//!
+//! Lives in its own test binary since ignoring `SIGCHLD` is process-wide.
+
+use std::process::Command;
+
+use pid_set::{PidSet, ReapState};
+
+#[test]
+fn sigchld_ignored() {
+    unsafe { libc::signal(libc::SIGCHLD, libc::SIG_IGN) };
+
+    let exit_cmd = || {
+        Command::new("sh")
+            .args(["-c", "sleep 0.2; exit 3"])
+            .spawn()
+            .unwrap()
+            .id()
+    };
+
+    for expect_autoreaped in [false, true] {
+        let pid = exit_cmd();
+        let mut pid_set = PidSet::builder()
+            .expect_autoreaped(expect_autoreaped)
+            .retain_exited(true)
+            .build([pid]);
+        let (succeeded, failed) = pid_set.wait_all_partitioned().unwrap();
+        // never a zombie
+        assert_eq!(pid_set.reap_state(pid), Some(ReapState::Reaped));
+        assert!(succeeded.is_empty());
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].code, None);
+        assert_eq!(failed[0].signal, None);
+        assert!(!failed[0].is_child);
+    }
+}
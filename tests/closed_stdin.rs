@@ -0,0 +1,33 @@
+//! Monitoring through a pidfd that gets file descriptor 0.
+//!
+//! Lives in its own test binary since closing stdin is process-wide.
+
+use std::process::{Command, Stdio};
+
+use pid_set::PidSet;
+
+fn is_open(fd: i32) -> bool {
+    unsafe { libc::fcntl(fd, libc::F_GETFD) >= 0 }
+}
+
+#[test]
+fn pidfd_on_stdin() {
+    let pid = Command::new("sleep")
+        .arg("0.1")
+        .stdin(Stdio::null())
+        .spawn()
+        .unwrap()
+        .id();
+    unsafe { libc::close(0) };
+
+    // pinning opens the pidfd before the epoll instance, so it gets the lowest
+    // free descriptor, 0
+    let mut pid_set = PidSet::new([pid]);
+    pid_set.pin().unwrap();
+    let fdinfo = std::fs::read_to_string("/proc/self/fdinfo/0").unwrap();
+    assert!(fdinfo.contains(&format!("Pid:\t{pid}")));
+
+    assert_eq!(pid_set.wait_n(1).unwrap(), [pid]);
+    assert!(!is_open(0), "the pidfd is closed once the PID exits");
+    assert!(pid_set.close().is_ok());
+}
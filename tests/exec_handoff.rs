@@ -0,0 +1,39 @@
+//! Monitoring handed over to a new process image across `execve`.
+//!
+//! The test binary re-executes itself to run the receiving side, selected by
+//! the handoff environment variable.
+
+use std::process::Command;
+
+use pid_set::PidSet;
+
+const HANDOFF_ENV: &str = "PID_SET_TEST_HANDOFF";
+
+#[test]
+fn receive_handoff() {
+    let Ok(handoff) = std::env::var(HANDOFF_ENV) else {
+        // only meaningful when executed by `handoff_across_exec`
+        return;
+    };
+    let mut pid_set = PidSet::from_inherited(&handoff).unwrap();
+    assert_eq!(pid_set.len(), 2);
+    assert_eq!(pid_set.wait_n(2).unwrap().len(), 2);
+    assert!(pid_set.close().is_ok());
+}
+
+#[test]
+fn handoff_across_exec() {
+    let pids: Vec<u32> = (0..2)
+        .map(|_| Command::new("sleep").arg("0.2").spawn().unwrap().id())
+        .collect();
+    let mut pid_set = PidSet::new(pids);
+    let handoff = pid_set.export_for_exec().unwrap();
+
+    let status = Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "receive_handoff"])
+        .env(HANDOFF_ENV, handoff)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(pid_set.close().is_ok());
+}
@@ -0,0 +1,54 @@
+//! Awaiting the exit of single PIDs.
+//!
+//! A single test, since it counts the open file descriptors of the process.
+
+use std::{
+    future::Future,
+    task::{Context, Poll, Waker},
+};
+
+use common::{block_on, open_fds, spawn};
+use pid_set::{PidSet, PidSetError};
+
+mod common;
+
+#[test]
+fn exit_future() {
+    let fds_before = open_fds();
+
+    let mut fast = spawn("0.1");
+    let mut slow = spawn("0.4");
+    let mut set = PidSet::new([fast.id(), slow.id()]);
+    assert!(matches!(
+        set.exit_future(1),
+        Err(PidSetError::PidNotFound(1))
+    ));
+
+    let mut fast_exit = Box::pin(set.exit_future(fast.id()).unwrap());
+    let slow_exit = set.exit_future(slow.id()).unwrap();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(fast_exit.as_mut().poll(&mut cx).is_pending());
+
+    let info = block_on(slow_exit);
+    assert_eq!((info.pid, info.code), (slow.id(), Some(0)));
+    // the faster one has exited by now
+    match fast_exit.as_mut().poll(&mut cx) {
+        Poll::Ready(info) => assert_eq!((info.pid, info.code), (fast.id(), Some(0))),
+        Poll::Pending => panic!("{} has exited", fast.id()),
+    }
+
+    // dropping a future leaves the PID monitored
+    let again = set.exit_future(fast.id()).unwrap();
+    drop(again);
+    assert_eq!(set.len(), 2);
+    set.wait_all().unwrap();
+    assert!(set.is_empty());
+
+    drop(fast_exit);
+    drop(set);
+    assert_eq!(open_fds(), fds_before);
+
+    for child in [&mut fast, &mut slow] {
+        child.wait().unwrap();
+    }
+}
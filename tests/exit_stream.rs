@@ -0,0 +1,81 @@
+//! Streaming the exits of a set from async code.
+//!
+//! A single test, since it counts the open file descriptors of the process.
+
+use std::{
+    process::Child,
+    task::{Context, Waker},
+    time::Duration,
+};
+
+use common::{block_on, open_fds, spawn};
+use pid_set::PidSet;
+
+mod common;
+
+#[test]
+fn exit_stream() {
+    let fds_before = open_fds();
+
+    // one exit per item, in exit order
+    let mut children: Vec<Child> = ["0.1", "0.2", "0.3"].map(spawn).into();
+    let pids: Vec<u32> = children.iter().map(Child::id).collect();
+    let mut stream = PidSet::new(pids.clone()).into_exit_stream();
+    let streamed: Vec<u32> = block_on(async {
+        let mut streamed = Vec::new();
+        while let Some(exit) = stream.next().await {
+            streamed.push(exit.unwrap().pid);
+        }
+        streamed
+    });
+    assert_eq!(streamed, pids);
+    assert!(block_on(stream.next()).is_none());
+    drop(stream);
+
+    // still one per item when they exit at once
+    let mut burst: Vec<Child> = ["0"; 4].map(spawn).into();
+    let burst_pids: Vec<u32> = burst.iter().map(Child::id).collect();
+    std::thread::sleep(Duration::from_millis(200));
+    let mut stream = PidSet::new(burst_pids.clone()).into_exit_stream();
+    for _ in 0..burst_pids.len() {
+        let exit = block_on(stream.next()).unwrap().unwrap();
+        assert!(burst_pids.contains(&exit.pid));
+    }
+    assert!(block_on(stream.next()).is_none());
+    drop(stream);
+    children.append(&mut burst);
+
+    // the whole burst in a single batch
+    let mut burst: Vec<Child> = ["0"; 4].map(spawn).into();
+    let mut burst_pids: Vec<u32> = burst.iter().map(Child::id).collect();
+    std::thread::sleep(Duration::from_millis(200));
+    let mut stream = PidSet::new(burst_pids.clone()).into_exit_stream().batched();
+    let mut batch: Vec<u32> = block_on(stream.next())
+        .unwrap()
+        .unwrap()
+        .iter()
+        .map(|exit| exit.pid)
+        .collect();
+    batch.sort_unstable();
+    burst_pids.sort_unstable();
+    assert_eq!(batch, burst_pids);
+    assert!(block_on(stream.next()).is_none());
+    drop(stream);
+    children.append(&mut burst);
+    assert!(block_on(PidSet::new([]).into_exit_stream().next()).is_none());
+    assert_eq!(open_fds(), fds_before);
+
+    // dropping a pending stream closes every file descriptor
+    let mut slow = spawn("5");
+    let mut stream = PidSet::new([slow.id()]).into_exit_stream();
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(stream.poll_next(&mut cx).is_pending());
+    assert!(open_fds() > fds_before);
+    drop(stream);
+    assert_eq!(open_fds(), fds_before);
+
+    slow.kill().unwrap();
+    for child in children.iter_mut().chain([&mut slow]) {
+        child.wait().unwrap();
+    }
+}
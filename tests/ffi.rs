@@ -0,0 +1,39 @@
+//! Round trip through the C API, see `examples/ffi/roundtrip.c` for the C side.
+
+#![cfg(feature = "ffi")]
+
+use std::process::Command;
+
+use pid_set::ffi::{pidset_add, pidset_close, pidset_new, pidset_wait_any};
+
+#[test]
+fn round_trip() {
+    let spawn = || Command::new("sleep").arg("0.1").spawn().unwrap().id();
+    let first = spawn();
+    let second = spawn();
+
+    unsafe {
+        let set = pidset_new(&first, 1);
+        assert!(!set.is_null());
+        assert_eq!(pidset_add(set, second), 0);
+        assert_eq!(pidset_add(set, 0), -libc::EINVAL);
+
+        let mut exited = [0, 0];
+        assert_eq!(pidset_wait_any(set, &mut exited[0]), 0);
+        assert_eq!(pidset_wait_any(set, &mut exited[1]), 0);
+        exited.sort();
+        let mut expected = [first, second];
+        expected.sort();
+        assert_eq!(exited, expected);
+
+        let mut pid = 0;
+        assert_eq!(pidset_wait_any(set, &mut pid), -libc::ECHILD);
+        assert_eq!(pidset_close(set), 0);
+    }
+
+    unsafe {
+        assert!(pidset_new(std::ptr::null(), 1).is_null());
+        assert_eq!(pidset_add(std::ptr::null_mut(), first), -libc::EINVAL);
+        assert_eq!(pidset_close(std::ptr::null_mut()), 0);
+    }
+}
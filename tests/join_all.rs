@@ -0,0 +1,41 @@
+//! Joining a set from async code.
+//!
+//! A single test, since it counts the open file descriptors of the process.
+
+use std::{
+    future::Future,
+    process::Child,
+    task::{Context, Waker},
+};
+
+use common::{block_on, open_fds, spawn};
+use pid_set::PidSet;
+
+mod common;
+
+#[test]
+fn join_all() {
+    let fds_before = open_fds();
+
+    let mut children: Vec<Child> = ["0.1", "0.2", "0.3"].map(spawn).into();
+    let pids: Vec<u32> = children.iter().map(Child::id).collect();
+    let exits = block_on(PidSet::new(pids.clone()).join_all()).unwrap();
+    assert_eq!(exits.iter().map(|info| info.pid).collect::<Vec<_>>(), pids);
+    assert!(exits.iter().all(|info| info.code == Some(0)));
+    assert!(block_on(PidSet::new([]).join_all()).unwrap().is_empty());
+    assert_eq!(open_fds(), fds_before);
+
+    // dropping a pending join closes every file descriptor
+    let mut slow = spawn("5");
+    let mut future = Box::pin(PidSet::new([slow.id()]).join_all());
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    assert!(open_fds() > fds_before);
+    drop(future);
+    assert_eq!(open_fds(), fds_before);
+
+    slow.kill().unwrap();
+    for child in children.iter_mut().chain([&mut slow]) {
+        child.wait().unwrap();
+    }
+}
@@ -0,0 +1,52 @@
+//! File descriptor cleanup when an `on_exit` hook panics.
+//!
+//! Lives in its own test binary so that no other test opens file descriptors
+//! while they are counted.
+
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    process::Command,
+};
+
+use pid_set::PidSet;
+
+fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").unwrap().count()
+}
+
+#[test]
+fn panicking_hook_does_not_leak() {
+    let before = open_fds();
+    let pids: Vec<u32> = (0..3)
+        .map(|_| Command::new("true").spawn().unwrap().id())
+        .collect();
+
+    let mut pid_set = PidSet::new(pids.clone());
+    let mut panicked = false;
+    pid_set.on_exit(Box::new(move |_, _| {
+        if !panicked {
+            panicked = true;
+            panic!("hook failure");
+        }
+    }));
+    assert!(catch_unwind(AssertUnwindSafe(|| pid_set.wait_all())).is_err());
+    assert!(!pid_set.is_empty());
+    // the remaining pidfds and the epoll instance are still open
+    assert!(open_fds() > before);
+
+    // the set is still usable, and reports the PID whose hook panicked
+    pid_set.wait_all().unwrap();
+    assert_eq!(pid_set.exited(), pids.len());
+    drop(pid_set);
+    assert_eq!(open_fds(), before);
+
+    // unwinding through the drop of the set closes everything as well
+    let pids = (0..3).map(|_| Command::new("true").spawn().unwrap().id());
+    let result = catch_unwind(|| {
+        let mut pid_set = PidSet::new(pids);
+        pid_set.on_exit(Box::new(|_, _| panic!("hook failure")));
+        let _ = pid_set.wait_all();
+    });
+    assert!(result.is_err());
+    assert_eq!(open_fds(), before);
+}
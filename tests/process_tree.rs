@@ -0,0 +1,73 @@
+//! Supervision of a whole process tree.
+//!
+//! A shell spawns three grandchildren that exit one after the other, waits for
+//! them and then exits itself. Every process of the tree is monitored: the
+//! grandchildren are not children of the test, so only their exit is observed,
+//! while the shell is a direct child whose status is collected.
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use pid_set::{ExitInfo, PidSet};
+
+/// Delay between two exits of the tree, in milliseconds, generous enough for a
+/// loaded machine.
+const STAGGER_MS: u32 = 300;
+
+fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").unwrap().count()
+}
+
+#[test]
+fn tree_teardown() {
+    let fds_before = open_fds();
+    let sleep = |steps: u32| format!("sleep {}", (steps * STAGGER_MS) as f64 / 1000.0);
+    let script = format!(
+        "{} & echo $!; {} & echo $!; {} & echo $!; wait; {}; exit 7",
+        sleep(1),
+        sleep(2),
+        sleep(3),
+        sleep(1)
+    );
+    let mut parent = Command::new("sh")
+        .args(["-c", &script])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let grandchildren: Vec<u32> = BufReader::new(parent.stdout.take().unwrap())
+        .lines()
+        .take(3)
+        .map(|line| line.unwrap().trim().parse().unwrap())
+        .collect();
+
+    let mut pid_set = PidSet::new(grandchildren.iter().copied().chain([parent.id()]));
+    let exits: Arc<Mutex<Vec<ExitInfo>>> = Arc::default();
+    let recorded = exits.clone();
+    pid_set.on_exit(Box::new(move |pid, info| {
+        recorded.lock().unwrap().push(info.unwrap_or(ExitInfo {
+            pid,
+            code: None,
+            signal: None,
+            is_child: false,
+        }))
+    }));
+    pid_set.wait_all().unwrap();
+    assert!(pid_set.is_empty());
+
+    let exits = exits.lock().unwrap();
+    let order: Vec<u32> = exits.iter().map(|info| info.pid).collect();
+    let expected: Vec<u32> = grandchildren.iter().copied().chain([parent.id()]).collect();
+    assert_eq!(order, expected, "the tree tears down leaves first");
+    assert!(exits[..3].iter().all(|info| !info.is_child));
+    let parent_exit = exits[3];
+    assert!(parent_exit.is_child);
+    assert_eq!(parent_exit.code, Some(7));
+
+    // the shell is left waitable for its owner
+    assert_eq!(parent.wait().unwrap().code(), Some(7));
+    pid_set.close().unwrap();
+    assert_eq!(open_fds(), fds_before);
+}
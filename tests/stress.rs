@@ -0,0 +1,72 @@
+//! Soak test spawning and reaping thousands of processes.
+//!
+//! Ignored by default, run it with `cargo test -- --ignored`.
+
+use std::{
+    collections::HashMap,
+    process::{Child, Command},
+    sync::{Arc, Mutex},
+};
+
+use pid_set::PidSet;
+
+const WAVES: usize = 20;
+const WAVE_SIZE: usize = 250;
+
+fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").unwrap().count()
+}
+
+#[test]
+#[ignore]
+fn spawn_and_reap_thousands() {
+    let fds_before = open_fds();
+
+    for _ in 0..WAVES {
+        let mut children: Vec<Child> = (0..WAVE_SIZE)
+            .map(|_| Command::new("true").spawn().unwrap())
+            .collect();
+        let mut pid_set = PidSet::new(children.iter().map(Child::id));
+
+        let seen = Arc::new(Mutex::new(HashMap::<u32, usize>::new()));
+        let recorded = seen.clone();
+        pid_set.on_exit(Box::new(move |pid, _| {
+            *recorded.lock().unwrap().entry(pid).or_default() += 1
+        }));
+        pid_set.wait_all().unwrap();
+        pid_set.close().unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), WAVE_SIZE);
+        assert!(seen.values().all(|count| *count == 1), "duplicate exit");
+        for child in &mut children {
+            child.wait().unwrap();
+        }
+    }
+
+    assert_eq!(open_fds(), fds_before, "file descriptors leaked");
+}
+
+/// Compares the wait loop with and without `EPOLLONESHOT`, printing the timings.
+#[test]
+#[ignore]
+fn bench_oneshot() {
+    for oneshot in [false, true] {
+        let mut children: Vec<Child> = (0..WAVE_SIZE)
+            .map(|_| Command::new("true").spawn().unwrap())
+            .collect();
+        let mut pid_set = PidSet::builder()
+            .oneshot(oneshot)
+            .build(children.iter().map(Child::id));
+        // let every child exit so that only the wait loop is measured
+        std::thread::sleep(std::time::Duration::from_millis(500));
+
+        let start = std::time::Instant::now();
+        pid_set.wait_all().unwrap();
+        println!("oneshot={oneshot}: {:?}", start.elapsed());
+
+        for child in &mut children {
+            child.wait().unwrap();
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Monitoring of reparented orphans, with and without `PR_SET_CHILD_SUBREAPER`.
+//!
+//! Lives in its own test binary since becoming a subreaper affects the whole
+//! process.
+
+use std::{
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+};
+
+use pid_set::{PidSet, PidSetError};
+
+/// Spawns a shell that leaves behind a process exiting with `code`, waits for
+/// the shell and returns the PID of the orphan.
+fn orphan(code: i32) -> u32 {
+    let mut shell = Command::new("sh")
+        .args(["-c", &format!("sh -c 'sleep 0.2; exit {code}' & echo $!")])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(shell.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    shell.wait().unwrap();
+    line.trim().parse().unwrap()
+}
+
+#[test]
+fn adopted_orphans() {
+    // reparented to init, or whichever subreaper is above the test: only its
+    // exit is observed
+    let pid = orphan(3);
+    let mut pid_set = PidSet::new([pid]);
+    let exits = pid_set.drain_wait_all().unwrap();
+    assert_eq!(exits.len(), 1);
+    assert!(!exits[0].is_child);
+    assert!(matches!(
+        pid_set.reap(pid),
+        Err(PidSetError::PidNotFound(reaped)) if reaped == pid
+    ));
+
+    // adopted by the test, it is a child like any other
+    assert_eq!(
+        unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) },
+        0
+    );
+    let pid = orphan(4);
+    pid_set.insert(pid).unwrap();
+    let exits = pid_set.drain_wait_all().unwrap();
+    assert_eq!(exits.len(), 1);
+    assert!(exits[0].is_child);
+    assert_eq!(exits[0].code, Some(4));
+    assert_eq!(pid_set.reap(pid).unwrap().code, Some(4));
+}
@@ -0,0 +1,73 @@
+//! Monitoring of a multi-threaded process.
+//!
+//! The test binary re-executes itself as the monitored process, selected by the
+//! helper environment variable. It has no test harness, so that the helper's
+//! main thread can exit before the others.
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use pid_set::{PidSet, PidSetError};
+
+const HELPER_ENV: &str = "PID_SET_TEST_THREADS";
+
+/// When the helper's threads exit, relative to its start.
+const THREAD_EXIT: Duration = Duration::from_millis(100);
+const MAIN_THREAD_EXIT: Duration = Duration::from_millis(200);
+const PROCESS_EXIT: Duration = Duration::from_millis(600);
+
+/// A thread exits, then the main thread, then the whole process from a last
+/// thread, which prints its thread ID first.
+fn helper() {
+    std::thread::spawn(|| std::thread::sleep(THREAD_EXIT));
+    std::thread::spawn(|| {
+        let mut stdout = std::io::stdout();
+        writeln!(stdout, "{}", unsafe { libc::gettid() }).unwrap();
+        stdout.flush().unwrap();
+        std::thread::sleep(PROCESS_EXIT);
+        std::process::exit(3);
+    });
+    std::thread::sleep(MAIN_THREAD_EXIT);
+    // only the calling thread, without unwinding as `pthread_exit` would
+    unsafe { libc::syscall(libc::SYS_exit, 0) };
+}
+
+fn main() {
+    if std::env::var_os(HELPER_ENV).is_some() {
+        return helper();
+    }
+
+    let start = Instant::now();
+    let mut child = Command::new(std::env::current_exe().unwrap())
+        .env(HELPER_ENV, "1")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pid = child.id();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let tid: u32 = line.trim().parse().unwrap();
+
+    // a thread ID is refused, naming its process
+    assert!(matches!(
+        PidSet::try_new([tid]),
+        Err(PidSetError::ThreadId { tid: t, tgid }) if t == tid && tgid == pid
+    ));
+
+    // neither the thread nor the main thread exiting is the process exiting
+    let mut pid_set = PidSet::try_new([pid]).unwrap();
+    std::thread::sleep(((MAIN_THREAD_EXIT + PROCESS_EXIT) / 2).saturating_sub(start.elapsed()));
+    assert_eq!(pid_set.try_wait_any().unwrap(), None);
+
+    let exits = pid_set.drain_wait_all().unwrap();
+    assert!(start.elapsed() >= PROCESS_EXIT);
+    assert_eq!(exits.len(), 1);
+    assert_eq!(exits[0].code, Some(3));
+    assert!(pid_set.close().is_ok());
+    child.wait().unwrap();
+}
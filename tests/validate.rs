@@ -0,0 +1,93 @@
+//! Detection of pidfds closed behind the set's back.
+//!
+//! Lives in its own test binary so that no other test reuses the descriptor
+//! number while it is closed; its tests take turns for the same reason.
+
+use std::{process::Command, sync::Mutex};
+
+use pid_set::{PidSet, PidSetError};
+
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// Finds the descriptor of the pidfd referring to `pid` in `/proc/self/fdinfo`.
+fn pidfd_of(pid: u32) -> i32 {
+    std::fs::read_dir("/proc/self/fdinfo")
+        .unwrap()
+        .filter_map(|entry| {
+            let entry = entry.unwrap();
+            let fdinfo = std::fs::read_to_string(entry.path()).ok()?;
+            fdinfo
+                .lines()
+                .any(|line| line == format!("Pid:\t{pid}"))
+                .then(|| entry.file_name().to_str()?.parse().ok())?
+        })
+        .next()
+        .unwrap()
+}
+
+#[test]
+fn closed_pidfd_reported() {
+    let _serial = SERIAL.lock().unwrap();
+    let spawn = || Command::new("sleep").arg("0.1").spawn().unwrap().id();
+    let (intact, closed) = (spawn(), spawn());
+    let mut pid_set = PidSet::try_new([intact, closed]).unwrap();
+    assert!(pid_set.validate().is_ok());
+
+    unsafe { libc::close(pidfd_of(closed)) };
+    let invalid = pid_set.validate().unwrap_err();
+    assert_eq!(invalid.len(), 1);
+    assert_eq!(invalid[0].0, closed);
+    assert_eq!(invalid[0].1.raw_os_error(), Some(libc::EBADF));
+
+    assert_eq!(pid_set.wait_n(1).unwrap(), [intact]);
+}
+
+#[test]
+fn validate_before_wait() {
+    let _serial = SERIAL.lock().unwrap();
+    let spawn = || Command::new("sleep").arg("0.1").spawn().unwrap().id();
+    let (intact, closed) = (spawn(), spawn());
+    let mut pid_set = PidSet::builder()
+        .validate_before_wait(true)
+        .build([intact, closed]);
+    pid_set.init().unwrap();
+
+    unsafe { libc::close(pidfd_of(closed)) };
+    let Err(PidSetError::InvalidPidFds(invalid)) = pid_set.wait_all() else {
+        panic!("the closed pidfd fails the wait");
+    };
+    assert_eq!(invalid.len(), 1);
+    assert_eq!(invalid[0].0, closed);
+    assert!(PidSetError::InvalidPidFds(invalid)
+        .to_string()
+        .contains(&format!("`{closed}`")));
+    // nothing was consumed
+    assert_eq!(pid_set.len(), 2);
+    // waits that do not block are not checked
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    assert_eq!(pid_set.try_wait_any().unwrap(), Some(intact));
+}
+
+#[test]
+fn close_strict_and_lenient() {
+    let _serial = SERIAL.lock().unwrap();
+    let spawn = || Command::new("sleep").arg("0.1").spawn().unwrap().id();
+    let (intact, closed) = (spawn(), spawn());
+    let pid_set = PidSet::try_new([intact, closed]).unwrap();
+    let intact_fd = pidfd_of(intact);
+    unsafe { libc::close(pidfd_of(closed)) };
+    let Err(PidSetError::InvalidPidFds(invalid)) = pid_set.close() else {
+        panic!("the closed pidfd fails a strict close");
+    };
+    assert_eq!(invalid.len(), 1);
+    assert_eq!(invalid[0].0, closed);
+    // closed all the same
+    assert_eq!(unsafe { libc::fcntl(intact_fd, libc::F_GETFD) }, -1);
+
+    let (intact, closed) = (spawn(), spawn());
+    let pid_set = PidSet::try_new([intact, closed]).unwrap();
+    let intact_fd = pidfd_of(intact);
+    unsafe { libc::close(pidfd_of(closed)) };
+    pid_set.close_lenient();
+    assert_eq!(unsafe { libc::fcntl(intact_fd, libc::F_GETFD) }, -1);
+}
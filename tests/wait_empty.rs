@@ -0,0 +1,55 @@
+//! Awaiting, from async code, that a set is drained.
+//!
+//! A single test, since it counts the open file descriptors of the process.
+
+use std::{
+    future::Future,
+    process::Child,
+    task::{Context, Poll, Waker},
+};
+
+use common::{block_on, open_fds, spawn};
+use pid_set::PidSet;
+
+mod common;
+
+#[test]
+fn wait_empty() {
+    let fds_before = open_fds();
+
+    let mut children: Vec<Child> = ["0.1", "0.2", "0.3"].map(spawn).into();
+    let mut pid_set = PidSet::builder()
+        .retain_exited(true)
+        .build(children.iter().map(Child::id));
+    block_on(pid_set.wait_empty()).unwrap();
+    assert!(pid_set.is_empty());
+    assert_eq!(pid_set.exited(), children.len());
+    assert!(children.iter().all(|child| pid_set
+        .exit_info(child.id())
+        .is_some_and(|info| info.success())));
+
+    // already empty, no wait
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(matches!(
+        Box::pin(pid_set.wait_empty()).as_mut().poll(&mut cx),
+        Poll::Ready(Ok(()))
+    ));
+    drop(pid_set);
+    assert_eq!(open_fds(), fds_before);
+
+    // dropping a pending barrier stops its helper thread, the set is kept
+    let mut slow = spawn("5");
+    let mut pid_set = PidSet::new([slow.id()]);
+    let mut future = Box::pin(pid_set.wait_empty());
+    assert!(future.as_mut().poll(&mut cx).is_pending());
+    drop(future);
+    assert_eq!(pid_set.len(), 1);
+
+    slow.kill().unwrap();
+    block_on(pid_set.wait_empty()).unwrap();
+    drop(pid_set);
+    assert_eq!(open_fds(), fds_before);
+    for child in children.iter_mut().chain([&mut slow]) {
+        child.wait().unwrap();
+    }
+}